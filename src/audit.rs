@@ -0,0 +1,460 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::data::{write_atomic, Datastore};
+use crate::errors::Errcode;
+use crate::storage::current_user;
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// First entry of the log, documents created before were not audited
+    LogStarted {
+        invoice_counter: usize,
+        quotation_counter: usize,
+    },
+    InvoiceCreated {
+        id: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        number: Option<String>,
+        recipient: String,
+        total_no_tax: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+    },
+    QuotationCreated {
+        id: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        number: Option<String>,
+        recipient: String,
+        total_no_tax: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+    },
+    QuotationInvoiced {
+        quotation: usize,
+        invoice: usize,
+    },
+    ContactAdded {
+        slug: String,
+    },
+    PaymentRecorded {
+        invoice: usize,
+        amount: f64,
+        date: NaiveDate,
+    },
+}
+
+impl AuditEvent {
+    /// Whether the change logged by this event is in the saved data
+    fn is_saved(&self, data: &Datastore) -> bool {
+        let invoice = |id: &usize| data.invoices.history.iter().find(|inv| inv.id == *id);
+        match self {
+            AuditEvent::LogStarted { .. } => true,
+            AuditEvent::InvoiceCreated { id, .. }
+            | AuditEvent::QuotationInvoiced { invoice: id, .. } => invoice(id).is_some(),
+            AuditEvent::QuotationCreated { id, .. } => data
+                .quotations
+                .history
+                .values()
+                .flatten()
+                .any(|(quote, _)| quote.id == *id),
+            AuditEvent::ContactAdded { slug } => data.contacts.contains(slug),
+            AuditEvent::PaymentRecorded {
+                invoice: id,
+                amount,
+                date,
+            } => invoice(id).is_some_and(|inv| {
+                inv.payments
+                    .iter()
+                    .any(|p| (p.amount == *amount) && (p.date == *date))
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuditEntryContent {
+    seq: usize,
+    timestamp: DateTime<Utc>,
+    user: String,
+    event: AuditEvent,
+    prev_hash: String,
+}
+
+impl AuditEntryContent {
+    fn hash(&self) -> Result<String, Errcode> {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_string(self)?.as_bytes());
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    content: AuditEntryContent,
+    hash: String,
+}
+
+/// Append-only log of all the changes made to the datastore, each entry contains
+/// the hash of the previous one so any modification breaks the chain.
+/// The entries are first written to a pending file before the data is saved, and moved
+/// to the log once it is, so a change cannot be saved without being logged.
+pub struct AuditLog {
+    fname: PathBuf,
+    start: Option<AuditEvent>,
+    pending: Option<String>,
+}
+
+impl AuditLog {
+    /// Opens the log of the datastore, it is started with the first change of an empty
+    /// datastore, the others need `init`.
+    /// Completes the log with the entries of a change interrupted after the data was saved.
+    pub fn open(datadir: &Path, data: &Datastore) -> Result<AuditLog, Errcode> {
+        let fname = datadir.join("audit").with_extension("log");
+        let start = if fname.exists() || !data.is_empty() {
+            None
+        } else {
+            Some(AuditEvent::LogStarted {
+                invoice_counter: data.invoices.id_counter,
+                quotation_counter: data.quotations.id_counter,
+            })
+        };
+        let mut log = AuditLog {
+            fname,
+            start,
+            pending: None,
+        };
+        log.recover(data)?;
+        Ok(log)
+    }
+
+    /// Opens the log only to verify it, nothing is written and the entries of an
+    /// interrupted change are left pending
+    pub fn open_read_only(datadir: &Path) -> AuditLog {
+        AuditLog {
+            fname: datadir.join("audit").with_extension("log"),
+            start: None,
+            pending: None,
+        }
+    }
+
+    /// Starts the log of a datastore where documents were saved before the log existed
+    pub fn init(datadir: &Path, data: &Datastore) -> Result<(), Errcode> {
+        let mut log = AuditLog::open(datadir, data)?;
+        if log.fname.exists() {
+            return Err(Errcode::InvalidConfig(
+                "audit",
+                "The audit log was already started".to_string(),
+            ));
+        }
+        log.start = None;
+        log.write_pending(&[AuditEvent::LogStarted {
+            invoice_counter: data.invoices.id_counter,
+            quotation_counter: data.quotations.id_counter,
+        }])?;
+        log.commit()
+    }
+
+    fn pending_fname(&self) -> PathBuf {
+        self.fname.with_extension("log.pending")
+    }
+
+    fn recover(&mut self, data: &Datastore) -> Result<(), Errcode> {
+        let pending_fname = self.pending_fname();
+        if !pending_fname.exists() {
+            return Ok(());
+        }
+        let content = std::fs::read_to_string(&pending_fname)?;
+        let saved = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .all(|line| {
+                serde_json::from_str::<AuditEntry>(line)
+                    .is_ok_and(|entry| entry.content.event.is_saved(data))
+            });
+        if saved {
+            println!("[*] Adding the entries of an interrupted change to the audit log");
+            self.pending = Some(content);
+            self.commit()
+        } else {
+            println!("[*] Discarding the audit entries of a change that was not saved");
+            self.abort()
+        }
+    }
+
+    fn read(&self) -> Result<Vec<Result<AuditEntry, serde_json::Error>>, Errcode> {
+        if !self.fname.exists() {
+            return Ok(vec![]);
+        }
+        Ok(std::fs::read_to_string(&self.fname)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str::<AuditEntry>)
+            .collect())
+    }
+
+    /// Writes the entries of the events to the pending file, to call before saving the data
+    pub fn prepare(&mut self, events: &[AuditEvent]) -> Result<(), Errcode> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        if !self.fname.exists() && self.start.is_none() {
+            return Err(Errcode::AuditLogMissing);
+        }
+        self.write_pending(events)
+    }
+
+    fn write_pending(&mut self, events: &[AuditEvent]) -> Result<(), Errcode> {
+        let (mut seq, mut prev_hash) = match self.read()?.pop() {
+            Some(last) => {
+                let last = last?;
+                (last.content.seq, last.hash)
+            }
+            None => (0, GENESIS_HASH.to_string()),
+        };
+
+        let user = current_user();
+        let mut buffer = String::new();
+        for event in self.start.take().iter().chain(events.iter()) {
+            seq += 1;
+            let content = AuditEntryContent {
+                seq,
+                timestamp: Utc::now(),
+                user: user.clone(),
+                event: event.clone(),
+                prev_hash,
+            };
+            let hash = content.hash()?;
+            prev_hash = hash.clone();
+            buffer += &serde_json::to_string(&AuditEntry { content, hash })?;
+            buffer += "\n";
+        }
+        write_atomic(&self.pending_fname(), buffer.as_bytes())?;
+        self.pending = Some(buffer);
+        Ok(())
+    }
+
+    /// Moves the pending entries to the log, once the data is saved
+    pub fn commit(&mut self) -> Result<(), Errcode> {
+        let Some(buffer) = self.pending.take() else {
+            return Ok(());
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.fname)?;
+        file.write_all(buffer.as_bytes())?;
+        file.sync_all()?;
+        std::fs::remove_file(self.pending_fname())?;
+        Ok(())
+    }
+
+    /// Drops the pending entries, when the data could not be saved
+    pub fn abort(&mut self) -> Result<(), Errcode> {
+        self.pending = None;
+        match std::fs::remove_file(self.pending_fname()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(test)]
+    fn append(&mut self, events: &[AuditEvent]) -> Result<(), Errcode> {
+        self.prepare(events)?;
+        self.commit()
+    }
+
+    /// Checks the integrity of the log and its consistency with the datastore,
+    /// returns the list of problems found
+    pub fn verify(&self, data: &Datastore) -> Result<Vec<String>, Errcode> {
+        let mut problems = vec![];
+        let mut prev_hash = GENESIS_HASH.to_string();
+        let mut first_audited_invoice = None;
+        let mut last_invoice: Option<usize> = None;
+        let mut logged_invoices = vec![];
+
+        for (n, entry) in self.read()?.into_iter().enumerate() {
+            let line = n + 1;
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    problems.push(format!("Line {line}: unreadable entry ({e})"));
+                    continue;
+                }
+            };
+            if entry.content.seq != line {
+                problems.push(format!(
+                    "Line {line}: sequence number {} instead of {line}",
+                    entry.content.seq
+                ));
+            }
+            if entry.content.prev_hash != prev_hash {
+                problems.push(format!("Line {line}: not chained to the previous entry"));
+            }
+            if entry.content.hash()? != entry.hash {
+                problems.push(format!("Line {line}: content was modified"));
+            }
+            prev_hash = entry.hash.clone();
+
+            match entry.content.event {
+                AuditEvent::LogStarted {
+                    invoice_counter, ..
+                } => {
+                    if line != 1 {
+                        problems.push(format!("Line {line}: log restarted"));
+                    }
+                    first_audited_invoice = Some(invoice_counter);
+                }
+                AuditEvent::InvoiceCreated { id, recipient, .. } => {
+                    let expected = last_invoice.map(|n| n + 1).or(first_audited_invoice);
+                    if expected.is_some_and(|exp| exp != id) {
+                        problems.push(format!(
+                            "Line {line}: invoice {id} created, expected invoice {}",
+                            expected.unwrap()
+                        ));
+                    }
+                    last_invoice = Some(id);
+                    logged_invoices.push((id, recipient));
+                }
+                _ => {}
+            }
+        }
+
+        if self.pending_fname().exists() {
+            problems.push(format!(
+                "Entries of a change in progress or interrupted are pending in {:?}, \
+                they are added to the log or discarded by the next command changing the data",
+                self.pending_fname()
+            ));
+        }
+        if first_audited_invoice.is_none() && self.fname.exists() {
+            problems.push("The start of the log is missing".to_string());
+        }
+        if !self.fname.exists() && !data.is_empty() {
+            problems.push(
+                "The log is missing, run `docgen init-audit` if it was never started".to_string(),
+            );
+        }
+        for inv in data.invoices.history.iter() {
+            let audited = first_audited_invoice.is_some_and(|first| inv.id >= first);
+            let logged = logged_invoices
+                .iter()
+                .any(|(id, recipient)| (*id == inv.id) && (recipient == &inv.recipient));
+            if audited && !logged {
+                problems.push(format!("Invoice {} is not in the audit log", inv.id));
+            }
+        }
+        for (id, _) in logged_invoices.iter() {
+            if !data.invoices.history.iter().any(|inv| inv.id == *id) {
+                problems.push(format!(
+                    "Invoice {id} of the audit log is missing from the data"
+                ));
+            }
+        }
+        Ok(problems)
+    }
+}
+
+#[test]
+fn audit_log_tampering() {
+    use crate::contact::ContactBook;
+    use crate::doctype::invoice::InvoiceSavedData;
+    use crate::doctype::quotation::QuotationSavedData;
+    use crate::rates::RateTable;
+
+    let dir = std::env::temp_dir().join(format!("docgen_audit_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let data = Datastore {
+        contacts: ContactBook::default(),
+        invoices: InvoiceSavedData::init(),
+        quotations: QuotationSavedData::init(),
+        audit: vec![],
+        rates: RateTable::default(),
+    };
+    let quote = |id| AuditEvent::QuotationCreated {
+        id,
+        number: None,
+        currency: None,
+        recipient: "acme".to_string(),
+        total_no_tax: 1234.5,
+    };
+
+    let mut log = AuditLog::open(&dir, &data).unwrap();
+    log.append(&[quote(1), quote(2)]).unwrap();
+    AuditLog::open(&dir, &data)
+        .unwrap()
+        .append(&[quote(3)])
+        .unwrap();
+    assert!(log.verify(&data).unwrap().is_empty());
+
+    let content = std::fs::read_to_string(&log.fname).unwrap();
+    std::fs::write(&log.fname, content.replace("1234.5", "234.5")).unwrap();
+    assert_eq!(log.verify(&data).unwrap().len(), 3);
+
+    // Invoice 2 was never logged, and none of them are in the datastore
+    std::fs::remove_file(&log.fname).unwrap();
+    let invoice = |id| AuditEvent::InvoiceCreated {
+        id,
+        number: None,
+        currency: None,
+        recipient: "acme".to_string(),
+        total_no_tax: 1.0,
+    };
+    let mut log = AuditLog::open(&dir, &data).unwrap();
+    log.append(&[invoice(1), invoice(3)]).unwrap();
+    let problems = log.verify(&data).unwrap();
+    assert_eq!(problems.len(), 3);
+    assert!(problems[0].contains("expected invoice 2"));
+
+    // The log of a datastore with documents is only started explicitly
+    std::fs::remove_file(&log.fname).unwrap();
+    let mut data = data;
+    data.contacts = serde_json::from_str(
+        r#"{"acme": {"slug": "acme", "name": "ACME SA", "address": "Paris",
+            "invoices": [], "quotations": []}}"#,
+    )
+    .unwrap();
+    let mut log = AuditLog::open(&dir, &data).unwrap();
+    assert_eq!(log.verify(&data).unwrap().len(), 1);
+    assert!(matches!(
+        log.append(&[quote(4)]),
+        Err(Errcode::AuditLogMissing)
+    ));
+    AuditLog::init(&dir, &data).unwrap();
+    assert!(AuditLog::init(&dir, &data).is_err());
+    let mut log = AuditLog::open(&dir, &data).unwrap();
+    assert!(log.verify(&data).unwrap().is_empty());
+
+    // Entries of a change interrupted before the data was saved are dropped, the others kept
+    log.prepare(&[AuditEvent::ContactAdded {
+        slug: "globex".to_string(),
+    }])
+    .unwrap();
+    let log = AuditLog::open(&dir, &data).unwrap();
+    assert!(!log.pending_fname().exists());
+    assert_eq!(log.read().unwrap().len(), 1);
+    let mut log = log;
+    log.prepare(&[AuditEvent::ContactAdded {
+        slug: "acme".to_string(),
+    }])
+    .unwrap();
+    let content = std::fs::read_to_string(&log.fname).unwrap();
+    // Verifying the log changes nothing, the pending entries are reported
+    let problems = AuditLog::open_read_only(&dir).verify(&data).unwrap();
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].contains("pending"));
+    assert!(log.pending_fname().exists());
+    assert_eq!(std::fs::read_to_string(&log.fname).unwrap(), content);
+    let log = AuditLog::open(&dir, &data).unwrap();
+    assert_eq!(log.read().unwrap().len(), 2);
+    assert!(log.verify(&data).unwrap().is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
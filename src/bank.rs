@@ -0,0 +1,415 @@
+use chrono::NaiveDate;
+
+use crate::errors::Errcode;
+use crate::rates::xml_attribute;
+
+/// Money received on the bank account, the debits of the statements are left out
+#[derive(Debug, Clone, PartialEq)]
+pub struct BankCredit {
+    /// Identifier given by the bank, or made from the content of the line if there is none
+    pub id: String,
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub currency: Option<String>,
+    pub payer: String,
+    /// Remittance information, where the payer usually writes the invoice number
+    pub reference: String,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StatementFormat {
+    /// ISO 20022 bank to customer statement
+    Camt,
+    Ofx,
+    Csv,
+}
+
+impl StatementFormat {
+    pub fn detect(content: &str) -> StatementFormat {
+        if content.contains("BkToCstmrStmt") {
+            StatementFormat::Camt
+        } else if content.contains("OFXHEADER") || content.contains("<OFX>") {
+            StatementFormat::Ofx
+        } else {
+            StatementFormat::Csv
+        }
+    }
+}
+
+/// Credits of a bank statement, in the order of the file
+pub fn import_statement(
+    content: &str,
+    format: Option<StatementFormat>,
+) -> Result<Vec<BankCredit>, Errcode> {
+    match format.unwrap_or_else(|| StatementFormat::detect(content)) {
+        StatementFormat::Camt => parse_camt(content),
+        StatementFormat::Ofx => parse_ofx(content),
+        StatementFormat::Csv => parse_csv(content),
+    }
+}
+
+fn invalid_statement(msg: String) -> Errcode {
+    Errcode::DataCorrupted("bank statement".to_string(), msg)
+}
+
+fn parse_amount(amount: &str) -> Result<f64, Errcode> {
+    let mut value: String = amount
+        .chars()
+        .filter(|c| !c.is_whitespace() && (*c != '\'') && (*c != '+'))
+        .collect();
+    // The last separator is the decimal one, the others group the thousands,
+    // unless only one kind is used and it splits groups of 3 digits ("1.234")
+    if let Some(pos) = value.rfind([',', '.']) {
+        let (int, dec) = value.split_at(pos);
+        let sep = &dec[..1];
+        let grouping = !int.contains(if sep == "," { '.' } else { ',' })
+            && (int.contains(sep) || dec.len() == 4);
+        value = if grouping {
+            value.replace(sep, "")
+        } else {
+            format!("{}.{}", int.replace([',', '.'], ""), &dec[1..])
+        };
+    }
+    value
+        .parse()
+        .map_err(|_| invalid_statement(format!("Invalid amount {amount:?}")))
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, Errcode> {
+    let date = date.trim();
+    ["%Y-%m-%d", "%d/%m/%Y", "%d.%m.%Y", "%Y%m%d"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(date, fmt).ok())
+        .ok_or_else(|| invalid_statement(format!("Invalid date {date:?}")))
+}
+
+/// Identifier of the lines of the files without one
+fn content_id(date: &NaiveDate, amount: f64, reference: &str) -> String {
+    format!("{date}|{amount:.2}|{reference}")
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Opening tag and content of each `name` element, elements of the same name can't be nested
+fn xml_elements<'a>(content: &'a str, name: &str) -> Vec<(&'a str, &'a str)> {
+    let mut res = vec![];
+    let (open, close) = (format!("<{name}"), format!("</{name}>"));
+    let mut rest = content;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        // Other tags starting with the same name, like <Amt> and <AmtDtls>
+        if !tag[open.len()..].starts_with([' ', '/']) && !tag[open.len()..].is_empty() {
+            rest = &rest[tag_end..];
+            continue;
+        }
+        if tag.ends_with('/') {
+            res.push((tag, ""));
+            rest = &rest[tag_end..];
+            continue;
+        }
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        res.push((tag, &rest[tag_end + 1..end]));
+        rest = &rest[end + close.len()..];
+    }
+    res
+}
+
+/// Text of the first element found following the path
+fn xml_text(content: &str, path: &[&str]) -> Option<String> {
+    let (first, others) = path.split_first()?;
+    let elements = xml_elements(content, first);
+    if others.is_empty() {
+        let (_, text) = elements.first()?;
+        return Some(unescape_xml(text.trim()));
+    }
+    elements
+        .iter()
+        .find_map(|(_, inner)| xml_text(inner, others))
+}
+
+fn parse_camt(content: &str) -> Result<Vec<BankCredit>, Errcode> {
+    let mut res = vec![];
+    for (_, entry) in xml_elements(content, "Ntry") {
+        if xml_text(entry, &["CdtDbtInd"]).as_deref() != Some("CRDT") {
+            continue;
+        }
+        // Reversals of previous entries
+        if xml_text(entry, &["RvslInd"]).as_deref() == Some("true") {
+            continue;
+        }
+        let (tag, amount) = xml_elements(entry, "Amt")
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid_statement("Entry without an amount".to_string()))?;
+        let amount: f64 = amount
+            .trim()
+            .parse()
+            .map_err(|_| invalid_statement(format!("Invalid amount {amount:?}")))?;
+        let date = xml_text(entry, &["BookgDt", "Dt"])
+            .or_else(|| xml_text(entry, &["BookgDt", "DtTm"]))
+            .or_else(|| xml_text(entry, &["ValDt", "Dt"]))
+            .ok_or_else(|| invalid_statement("Entry without a date".to_string()))?;
+        let date = parse_date(&date.chars().take(10).collect::<String>())?;
+        // The name of the debtor is in a Pty element since the version 8 of the format
+        let payer = xml_text(entry, &["RltdPties", "Dbtr", "Nm"])
+            .or_else(|| xml_text(entry, &["RltdPties", "Dbtr", "Pty", "Nm"]))
+            .unwrap_or_default();
+        let mut reference: Vec<String> = xml_elements(entry, "Ustrd")
+            .into_iter()
+            .map(|(_, text)| unescape_xml(text.trim()))
+            .collect();
+        reference.extend(xml_text(entry, &["RmtInf", "Strd", "CdtrRefInf", "Ref"]));
+        if reference.is_empty() {
+            reference.extend(xml_text(entry, &["AddtlNtryInf"]));
+        }
+        let reference = reference.join(" ");
+        let id = xml_text(entry, &["AcctSvcrRef"])
+            .or_else(|| xml_text(entry, &["NtryRef"]))
+            .unwrap_or_else(|| content_id(&date, amount, &reference));
+        res.push(BankCredit {
+            id,
+            date,
+            amount,
+            currency: xml_attribute(tag, "Ccy").map(|c| c.to_string()),
+            payer,
+            reference,
+        });
+    }
+    Ok(res)
+}
+
+/// Value of an OFX element, the closing tags are optional in the version 1 of the format
+fn ofx_field(block: &str, name: &str) -> Option<String> {
+    let start = block.find(&format!("<{name}>"))? + name.len() + 2;
+    let value = &block[start..];
+    let value = value[..value.find('<').unwrap_or(value.len())].trim();
+    (!value.is_empty()).then(|| unescape_xml(value))
+}
+
+fn parse_ofx(content: &str) -> Result<Vec<BankCredit>, Errcode> {
+    let currency = ofx_field(content, "CURDEF");
+    let mut res = vec![];
+    for block in content.split("<STMTTRN>").skip(1) {
+        let block = &block[..block.find("</STMTTRN>").unwrap_or(block.len())];
+        let amount = ofx_field(block, "TRNAMT")
+            .ok_or_else(|| invalid_statement("Transaction without an amount".to_string()))?;
+        let amount = parse_amount(&amount)?;
+        if amount <= 0.0 {
+            continue;
+        }
+        let date = ofx_field(block, "DTPOSTED")
+            .ok_or_else(|| invalid_statement("Transaction without a date".to_string()))?;
+        let date = parse_date(&date.chars().take(8).collect::<String>())?;
+        let reference = ofx_field(block, "MEMO").unwrap_or_default();
+        res.push(BankCredit {
+            id: ofx_field(block, "FITID").unwrap_or_else(|| content_id(&date, amount, &reference)),
+            date,
+            amount,
+            currency: currency.clone(),
+            payer: ofx_field(block, "NAME").unwrap_or_default(),
+            reference,
+        });
+    }
+    Ok(res)
+}
+
+fn split_csv_line(line: &str, sep: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && (chars.peek() == Some(&'"')) => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if (c == sep) && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Names of the columns used in the CSV exports of the banks, in lower case
+const CSV_COLUMNS: [(&str, &[&str]); 6] = [
+    (
+        "date",
+        &[
+            "date",
+            "booking date",
+            "date opération",
+            "date operation",
+            "buchungstag",
+            "fecha",
+        ],
+    ),
+    (
+        "amount",
+        &[
+            "amount", "credit", "montant", "crédit", "betrag", "haben", "importe",
+        ],
+    ),
+    (
+        "payer",
+        &[
+            "payer",
+            "name",
+            "counterparty",
+            "tiers",
+            "auftraggeber",
+            "ordenante",
+        ],
+    ),
+    (
+        "reference",
+        &[
+            "reference",
+            "description",
+            "label",
+            "libellé",
+            "libelle",
+            "memo",
+            "verwendungszweck",
+            "concepto",
+        ],
+    ),
+    ("currency", &["currency", "devise", "währung", "moneda"]),
+    ("id", &["id", "transaction id", "référence banque"]),
+];
+
+fn parse_csv(content: &str) -> Result<Vec<BankCredit>, Errcode> {
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid_statement("Empty file".to_string()))?;
+    let sep = if header.matches(';').count() > header.matches(',').count() {
+        ';'
+    } else {
+        ','
+    };
+    let header = split_csv_line(header.trim_start_matches('\u{feff}'), sep);
+    let column = |name: &str| {
+        let (_, aliases) = CSV_COLUMNS.iter().find(|(col, _)| *col == name).unwrap();
+        header
+            .iter()
+            .position(|h| aliases.contains(&h.to_lowercase().as_str()))
+    };
+    let missing = |name| invalid_statement(format!("No {name} column in {header:?}"));
+    let date_col = column("date").ok_or_else(|| missing("date"))?;
+    let amount_col = column("amount").ok_or_else(|| missing("amount"))?;
+    let (payer_col, reference_col) = (column("payer"), column("reference"));
+    let (currency_col, id_col) = (column("currency"), column("id"));
+
+    let mut res = vec![];
+    for line in lines {
+        let fields = split_csv_line(line, sep);
+        let field = |col: Option<usize>| {
+            col.and_then(|c| fields.get(c))
+                .filter(|f| !f.is_empty())
+                .cloned()
+        };
+        let Some(amount) = field(Some(amount_col)) else {
+            continue;
+        };
+        let amount = parse_amount(&amount)?;
+        if amount <= 0.0 {
+            continue;
+        }
+        let date = parse_date(&field(Some(date_col)).unwrap_or_default())?;
+        let reference = field(reference_col).unwrap_or_default();
+        res.push(BankCredit {
+            id: field(id_col).unwrap_or_else(|| content_id(&date, amount, &reference)),
+            date,
+            amount,
+            currency: field(currency_col),
+            payer: field(payer_col).unwrap_or_default(),
+            reference,
+        });
+    }
+    Ok(res)
+}
+
+#[test]
+fn bank_statements_import() {
+    let camt = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02"><BkToCstmrStmt><Stmt>
+        <Ntry>
+            <Amt Ccy="EUR">1200.00</Amt><CdtDbtInd>CRDT</CdtDbtInd>
+            <BookgDt><Dt>2026-01-15</Dt></BookgDt><AcctSvcrRef>BK-1</AcctSvcrRef>
+            <NtryDtls><TxDtls><AmtDtls><TxAmt><Amt Ccy="EUR">1200.00</Amt></TxAmt></AmtDtls>
+            <RltdPties><Dbtr><Nm>ACME &amp; Co</Nm></Dbtr></RltdPties>
+            <RmtInf><Ustrd>Facture F00001</Ustrd></RmtInf></TxDtls></NtryDtls>
+        </Ntry>
+        <Ntry>
+            <Amt Ccy="EUR">35.00</Amt><CdtDbtInd>DBIT</CdtDbtInd>
+            <BookgDt><Dt>2026-01-16</Dt></BookgDt>
+        </Ntry>
+        </Stmt></BkToCstmrStmt></Document>"#;
+    let credits = import_statement(camt, None).unwrap();
+    assert_eq!(credits.len(), 1);
+    assert_eq!(credits[0].id, "BK-1");
+    assert_eq!(credits[0].amount, 1200.0);
+    assert_eq!(credits[0].currency.as_deref(), Some("EUR"));
+    assert_eq!(credits[0].payer, "ACME & Co");
+    assert_eq!(credits[0].reference, "Facture F00001");
+
+    let ofx = "OFXHEADER:100\nDATA:OFXSGML\n<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS>
+        <CURDEF>EUR
+        <BANKTRANLIST>
+        <STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20260120120000<TRNAMT>500,00<FITID>42
+        <NAME>INITECH<MEMO>F00002</STMTTRN>
+        <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20260121<TRNAMT>-20.00<FITID>43</STMTTRN>
+        </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>";
+    let credits = import_statement(ofx, None).unwrap();
+    assert_eq!(credits.len(), 1);
+    assert_eq!(
+        (
+            credits[0].id.as_str(),
+            credits[0].amount,
+            credits[0].payer.as_str()
+        ),
+        ("42", 500.0, "INITECH")
+    );
+    assert_eq!(
+        credits[0].date,
+        NaiveDate::from_ymd_opt(2026, 1, 20).unwrap()
+    );
+
+    let csv = "Date;Libellé;Montant;Devise\n\
+        02/02/2026;\"VIR ACME; F00003\";1 234,50;EUR\n\
+        03/02/2026;CB SHOP;-12,00;EUR\n";
+    let credits = import_statement(csv, None).unwrap();
+    assert_eq!(credits.len(), 1);
+    assert_eq!(credits[0].amount, 1234.5);
+    assert_eq!(credits[0].reference, "VIR ACME; F00003");
+    assert_eq!(credits[0].id, "2026-02-02|1234.50|VIR ACME; F00003");
+    assert!(import_statement("Day;Value\n1;2\n", None).is_err());
+
+    for (text, value) in [
+        ("1,234", 1234.0),
+        ("1.234", 1234.0),
+        ("1,234,567", 1234567.0),
+        ("1.234,56", 1234.56),
+        ("1,234.56", 1234.56),
+        ("1'234.50", 1234.5),
+        ("12,5", 12.5),
+        ("12.50", 12.5),
+    ] {
+        assert_eq!(parse_amount(text).unwrap(), value, "{text}");
+    }
+}
@@ -1,7 +1,13 @@
-use crate::{config::ConfigStore, data::Transaction, lang::LangDict};
+use crate::currency::AmountFormat;
+use crate::data::Transaction;
+use crate::doctype::snapshot::{BankDetails, Issuer, TaxSettings};
+use crate::lang::LangDict;
+use crate::rates::Conversion;
 
 pub fn sanitize(data: &str) -> String {
-    data.replace('@', "\\@").replace('#', "\\#")
+    data.replace('@', "\\@")
+        .replace('#', "\\#")
+        .replace('$', "\\$")
 }
 
 pub fn write_page_settings(buffer: &mut String, footer: &str) {
@@ -19,20 +25,17 @@ pub fn write_page_settings(buffer: &mut String, footer: &str) {
     *buffer += "#let sep_par() = 28pt\n";
 }
 
-pub fn generate_header(cfg: &ConfigStore, source: &mut String) {
-    let logo_path = cfg.get_company("logo_path");
+pub fn generate_header(issuer: &Issuer, source: &mut String) {
+    let logo_path = &issuer.logo_path;
     let logo = if logo_path.is_empty() {
         "".to_string()
     } else {
         format!("#image(\"{logo_path}\", width: logo_width())")
     };
 
-    let writing_logo_path = cfg.get_company("logo_writing");
+    let writing_logo_path = &issuer.logo_writing;
     let writing_logo = if writing_logo_path.is_empty() {
-        format!(
-            "#text(company_name_font_size())[*{}*]",
-            cfg.get_company("name")
-        )
+        format!("#text(company_name_font_size())[*{}*]", issuer.name)
     } else {
         format!("#image(\"{writing_logo_path}\", width: logo_width())")
     };
@@ -50,22 +53,26 @@ pub fn generate_header(cfg: &ConfigStore, source: &mut String) {
         "#align(left)[
         {} \\ {} \\ {} \\ {} \\ SIRET: {}
     ]\n",
-        sanitize(&cfg.get_company("person_name")),
-        sanitize(&cfg.get_company("address")),
-        sanitize(&cfg.get_company("email")),
-        sanitize(&cfg.get_company("legal_status")),
-        sanitize(&cfg.get_company("siret_number")),
+        sanitize(&issuer.person_name),
+        sanitize(&issuer.address),
+        sanitize(&issuer.email),
+        sanitize(&issuer.legal_status),
+        sanitize(&issuer.siret_number),
     )
     .as_str();
     *source += "\n";
 }
 
-pub fn generate_transaction_table(source: &mut String, tx: &[Transaction], lang: &LangDict) -> f64 {
+pub fn generate_transaction_table(
+    source: &mut String,
+    tx: &[Transaction],
+    lang: &LangDict,
+    amounts: &AmountFormat,
+) -> f64 {
     let word_desc = lang.get_doctype_word("general", "tx_item_description");
     let word_units = lang.get_doctype_word("general", "tx_units");
     let word_ppu = lang.get_doctype_word("general", "tx_price_per_unit");
     let word_total = lang.get_doctype_word("general", "total_price_no_tax");
-    let curr_sym = lang.get_doctype_word("general", "currency_symbol");
     *source += format!(
         "#table(
         stroke: table_color(),
@@ -80,8 +87,11 @@ pub fn generate_transaction_table(source: &mut String, tx: &[Transaction], lang:
         let total = units * ppu;
         *source += format!(
             "
-            \"{descr}\", \"{units}\", \"{ppu:.2} {curr_sym}\", \"{total:.2} {curr_sym}\",
-        "
+            \"{descr}\", \"{}\", \"{}\", \"{}\",
+        ",
+            amounts.locale.quantity(*units),
+            amounts.format(*ppu),
+            amounts.format(total),
         )
         .as_str();
         total_price += total;
@@ -95,18 +105,19 @@ pub fn generate_summary_table(
     source: &mut String,
     total_price: f64,
     lang: &LangDict,
-    cfg: &ConfigStore,
+    taxes: &TaxSettings,
+    amounts: &AmountFormat,
+    conversion: Option<&Conversion>,
 ) {
-    let curr_sym = lang.get_doctype_word("general", "currency_symbol");
-    let (tax_fmt, tax_amnt) = if cfg.get_bool("taxes", "tax_applicable") {
-        let tax_rate: f64 = cfg.get_float("taxes", "tax_rate");
+    let (tax_fmt, tax_amnt) = if taxes.tax_applicable {
+        let tax_rate: f64 = taxes.tax_rate;
         let amnt = total_price * tax_rate;
         (
             format!(
-                "[*{} {:.2}%*], [{:.2} {curr_sym}]",
+                "[*{} {}%*], [{}]",
                 lang.get_doctype_word("general", "tax_name"),
-                tax_rate * 100.0,
-                amnt,
+                amounts.locale.decimal(tax_rate * 100.0, 2),
+                sanitize(&amounts.format(amnt)),
             ),
             amnt,
         )
@@ -120,40 +131,100 @@ pub fn generate_summary_table(
         )
     };
 
+    // Amounts in the accounting currency, for the documents written in another one
+    let conversion_fmt = if let Some(conv) = conversion {
+        let code = &conv.rate.currency;
+        let converted_tax = if taxes.tax_applicable {
+            format!(
+                "[*{} ({code})*], [{}],",
+                lang.get_doctype_word("general", "tax_name"),
+                sanitize(&conv.amounts.format(tax_amnt * conv.rate.rate)),
+            )
+        } else {
+            "".to_string()
+        };
+        format!(
+            "[*{} {}*], [1 {} = {} {code}],
+        {converted_tax}
+        [*{} ({code})*], [{}],",
+            lang.get_doctype_word("general", "exchange_rate"),
+            conv.rate.date.format("%Y-%m-%d"),
+            amounts.currency.code,
+            conv.amounts.locale.decimal(conv.rate.rate, 6),
+            lang.get_doctype_word("general", "total_price_with_tax"),
+            sanitize(
+                &conv
+                    .amounts
+                    .format((total_price + tax_amnt) * conv.rate.rate)
+            ),
+        )
+    } else {
+        "".to_string()
+    };
+
     *source += format!(
         "#table(
         stroke: table_color(),
         columns: (auto, auto),
-        [*{}*], [{total_price:.2} {curr_sym}],
+        [*{}*], [{}],
         {tax_fmt},
-        [*{}*], [{:.2} {curr_sym}],
+        [*{}*], [{}],
+        {conversion_fmt}
     )",
         lang.get_doctype_word("general", "total_price_no_tax"),
+        sanitize(&amounts.format(total_price)),
         lang.get_doctype_word("general", "total_price_with_tax"),
-        total_price + tax_amnt
+        sanitize(&amounts.format(total_price + tax_amnt)),
     )
     .as_str();
     *source += "\n";
 }
 
-pub fn generate_iban(source: &mut String, lang: &LangDict, cfg: &ConfigStore) {
-    *source += format!(
-        "
-        === {}
-
-        #table(
+/// Bank details, with the payment QR code image next to them if set
+pub fn generate_iban(
+    source: &mut String,
+    lang: &LangDict,
+    bank: &BankDetails,
+    qr_code: Option<&str>,
+) {
+    let table = format!(
+        "table(
             stroke: table_color(),
             columns: (auto, auto),
             [*{}*], [{}],
             [*IBAN*], [{}],
             [*BIC*], [{}],
         )",
-        lang.get_doctype_word("general", "iban_title"),
         lang.get_doctype_word("general", "iban_bank"),
-        cfg.get_str("bank", "name"),
-        cfg.get_str("bank", "iban"),
-        cfg.get_str("bank", "bic"),
+        bank.name,
+        bank.iban,
+        bank.bic,
+    );
+    *source += format!(
+        "
+        === {}
+
+        ",
+        lang.get_doctype_word("general", "iban_title"),
     )
     .as_str();
+    match qr_code {
+        Some(path) => {
+            *source += format!(
+                "#grid(
+            columns: (auto, 1fr),
+            column-gutter: 10%,
+            align(left + horizon, {table}),
+            align(center)[
+                #image(\"{path}\", width: 3cm) \\
+                #text(footer_font_size())[{}]
+            ],
+        )",
+                lang.get_doctype_word("general", "payment_qr"),
+            )
+            .as_str();
+        }
+        None => *source += format!("#{table}").as_str(),
+    }
     *source += "\n";
 }
@@ -1,9 +1,14 @@
 use std::path::PathBuf;
 
+use serde::{Deserialize, Serialize};
 use toml::map::Map;
 
 use crate::errors::Errcode;
+use crate::identifiers::check_config;
+use crate::style::Style;
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(transparent)]
 pub struct ConfigStore {
     data: Map<String, toml::Value>,
 }
@@ -38,6 +43,12 @@ impl ConfigStore {
             .unwrap_or_else(|| panic!("Unable to convert {key}:{data} to boolean"))
     }
 
+    pub fn get_int(&self, key: &str, data: &str) -> i64 {
+        self.get_toml_value(key, data)
+            .as_integer()
+            .unwrap_or_else(|| panic!("Unable to convert {key}:{data} to integer"))
+    }
+
     pub fn get_float(&self, key: &str, data: &str) -> f64 {
         self.get_toml_value(key, data)
             .as_float()
@@ -49,27 +60,141 @@ impl ConfigStore {
             .as_str()
             .unwrap_or_else(|| panic!("Unable to convert {key}:{data} to str"))
     }
+
+    pub fn profiles(&self) -> Vec<String> {
+        self.data
+            .get("profiles")
+            .and_then(|p| p.as_table())
+            .map(|p| p.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Configuration of an issuer profile, its tables override the settings of the
+    /// main configuration key by key
+    pub fn for_profile(&self, name: &str) -> Result<ConfigStore, Errcode> {
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || (c == '-') || (c == '_'))
+        {
+            return Err(Errcode::InvalidConfig(
+                "profiles",
+                format!("Invalid profile name {name:?}"),
+            ));
+        }
+        let Some(profile) = self
+            .data
+            .get("profiles")
+            .and_then(|p| p.get(name))
+            .and_then(|p| p.as_table())
+        else {
+            return Err(Errcode::ProfileNotFound(name.to_string()));
+        };
+
+        let mut data = self.data.clone();
+        data.remove("profiles");
+        for (key, val) in profile.iter() {
+            match (data.get_mut(key), val) {
+                (Some(toml::Value::Table(table)), toml::Value::Table(overrides)) => {
+                    for (subkey, subval) in overrides.iter() {
+                        table.insert(subkey.clone(), subval.clone());
+                    }
+                }
+                _ => {
+                    data.insert(key.clone(), val.clone());
+                }
+            }
+        }
+        Ok(ConfigStore { data })
+    }
+
+    /// Style settings set by a profile on top of the style file
+    pub fn style_overrides(&self) -> Style {
+        self.data
+            .get("style")
+            .and_then(|s| s.as_table())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+const DEFAULT_CONFIG: &str = include_str!("../default/config.toml");
+
+fn default_config() -> Map<String, toml::Value> {
+    let default_config: toml::Value = toml::from_str(DEFAULT_CONFIG).unwrap();
+    default_config.as_table().unwrap().to_owned()
+}
+
+/// Adds the settings missing from a configuration, with their default value
+fn add_missing_settings(config: &mut Map<String, toml::Value>) {
+    for (key, val) in default_config().into_iter() {
+        match (config.get_mut(&key), val) {
+            (None, val) => {
+                config.insert(key, val);
+            }
+            // New settings added to an existing table
+            (Some(toml::Value::Table(table)), toml::Value::Table(default_table)) => {
+                for (subkey, subval) in default_table.into_iter() {
+                    table.entry(subkey).or_insert(subval);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ConfigStore {
+    /// Configurations saved with older documents lack the settings added since
+    pub fn with_missing_settings(&self) -> ConfigStore {
+        let mut data = self.data.clone();
+        add_missing_settings(&mut data);
+        ConfigStore { data }
+    }
 }
 
 pub fn import_config(config_file: &PathBuf) -> Result<ConfigStore, Errcode> {
-    let default_config_str = include_str!("../default/config.toml");
-    let default_config: toml::Value = toml::from_str(default_config_str)?;
-    let default_config = default_config.as_table().unwrap().to_owned();
     if !config_file.exists() {
-        std::fs::write(config_file, default_config_str)?;
+        std::fs::write(config_file, DEFAULT_CONFIG)?;
         return Ok(ConfigStore {
-            data: default_config,
+            data: default_config(),
         });
     }
     assert!(config_file.is_file());
 
     let config: toml::Value = toml::from_str(std::fs::read_to_string(config_file)?.as_str())?;
     let mut config = config.as_table().unwrap().to_owned();
-    for (key, val) in default_config.into_iter() {
-        if !config.contains_key(&key) {
-            config.insert(key, val);
-        }
-    }
+    add_missing_settings(&mut config);
     std::fs::write(config_file, toml::to_string(&config)?)?;
-    Ok(ConfigStore { data: config })
+    let config = ConfigStore { data: config };
+    check_config(&config)?;
+    Ok(config)
+}
+
+#[test]
+fn profile_overrides() {
+    let cfg: toml::Value = toml::from_str(
+        r#"
+        [company]
+        name = "SARL"
+        email = "contact@sarl.fr"
+        [profiles.freelance.company]
+        name = "EI"
+        [profiles.freelance.style]
+        font_name = "Inter"
+        "#,
+    )
+    .unwrap();
+    let cfg = ConfigStore {
+        data: cfg.as_table().unwrap().to_owned(),
+    };
+    assert_eq!(cfg.profiles(), vec!["freelance".to_string()]);
+    assert!(cfg.style_overrides().is_empty());
+
+    let freelance = cfg.for_profile("freelance").unwrap();
+    assert_eq!(freelance.get_company("name"), "EI");
+    assert_eq!(freelance.get_company("email"), "contact@sarl.fr");
+    assert!(freelance.profiles().is_empty());
+    assert!(freelance.style_overrides().contains_key("font_name"));
+
+    assert!(cfg.for_profile("other").is_err());
+    assert!(cfg.for_profile("../free").is_err());
 }
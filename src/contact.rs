@@ -5,13 +5,16 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
+use crate::currency::Currency;
 use crate::errors::Errcode;
-use crate::interface::ask::ask_user_nonempty;
+use crate::identifiers::{check_vat_number, compact};
+use crate::interface::ask::{ask_user, ask_user_nonempty, ask_user_parse};
 
+#[derive(Serialize, Deserialize, Default)]
 pub struct ContactBook(HashMap<String, Contact>);
 
 impl ContactBook {
-    fn fname(root: &Path) -> PathBuf {
+    pub fn fname(root: &Path) -> PathBuf {
         root.join("contacts").with_extension("json")
     }
 
@@ -26,6 +29,10 @@ impl ContactBook {
         }
     }
 
+    pub fn contains(&self, slug: &String) -> bool {
+        self.0.contains_key(slug)
+    }
+
     pub fn get<'a>(&'a self, slug: &String) -> &'a Contact {
         self.0
             .get(slug)
@@ -40,34 +47,12 @@ impl ContactBook {
             .unwrap()
     }
 
-    pub fn import(root: &Path) -> ContactBook {
-        let fname = Self::fname(root);
-        let mut book = ContactBook(HashMap::new());
-        if fname.is_file() {
-            let json_str = std::fs::read_to_string(Self::fname(root))
-                .expect("Unable to read data from file {fname}");
-            let data = serde_json::from_str::<serde_json::Value>(&json_str)
-                .expect("Unable to load JSON data from file {fname}");
-            let data = data.as_object().unwrap().to_owned();
-            for (k, v) in data.into_iter() {
-                book.0.insert(
-                    k,
-                    serde_json::from_value::<Contact>(v)
-                        .expect("Unable to deserialize Contact from value {v:?}"),
-                );
-            }
-        }
-        book
+    pub fn insert(&mut self, slug: String, contact: Contact) {
+        self.0.insert(slug, contact);
     }
 
-    pub fn export(&self, root: &Path) -> Result<(), Errcode> {
-        let mut map = serde_json::Map::new();
-        for (k, v) in self.0.iter() {
-            map.insert(k.clone(), serde_json::to_value(v)?);
-        }
-        let json_str = serde_json::to_string_pretty(&map)?;
-        std::fs::write(Self::fname(root), json_str)?;
-        Ok(())
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Contact)> {
+        self.0.iter()
     }
 }
 
@@ -78,6 +63,18 @@ pub struct Contact {
     pub address: String,
     pub invoices: Vec<usize>,
     pub quotations: Vec<usize>,
+    /// Issuer profile used by default for the documents sent to this contact
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// ISO 4217 code of the documents sent to this contact, instead of the default one
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Language of the documents sent to this contact, instead of the default one
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// EU VAT number, with its country code
+    #[serde(default)]
+    pub vat_number: Option<String>,
 }
 
 impl Contact {
@@ -85,12 +82,35 @@ impl Contact {
         let slug = slug.unwrap_or_else(Self::ask_slug);
         let name = ask_user_nonempty("Name: ".to_string());
         let address = ask_user_nonempty("Address: ".to_string());
+        let currency: Option<Currency> =
+            ask_user_parse("Currency (empty to use the default one): ".to_string());
+        let lang =
+            Some(ask_user("Language (empty to use the default one): ")).filter(|l| !l.is_empty());
+        let vat_number = Self::ask_vat_number();
         Contact {
             slug,
             name,
             address,
             invoices: vec![],
             quotations: vec![],
+            profile: None,
+            currency: currency.map(|c| c.code),
+            lang,
+            vat_number,
+        }
+    }
+
+    /// Asks again until the number is valid or left empty
+    fn ask_vat_number() -> Option<String> {
+        loop {
+            let vat = ask_user("EU VAT number (empty if none): ");
+            if vat.is_empty() {
+                return None;
+            }
+            match check_vat_number(&vat) {
+                Ok(()) => return Some(compact(&vat)),
+                Err(reason) => println!("Invalid VAT number, {reason}"),
+            }
         }
     }
 
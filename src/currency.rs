@@ -0,0 +1,199 @@
+use std::str::FromStr;
+
+use crate::errors::Errcode;
+use crate::lang::LangDict;
+
+// Code, symbol, number of decimals
+const KNOWN_CURRENCIES: &[(&str, &str, usize)] = &[
+    ("EUR", "€", 2),
+    ("USD", "$", 2),
+    ("GBP", "£", 2),
+    ("CHF", "CHF", 2),
+    ("CAD", "CA$", 2),
+    ("AUD", "A$", 2),
+    ("JPY", "¥", 0),
+    ("CNY", "CN¥", 2),
+    ("SEK", "kr", 2),
+    ("NOK", "kr", 2),
+    ("DKK", "kr", 2),
+    ("PLN", "zł", 2),
+    ("CZK", "Kč", 2),
+    ("HUF", "Ft", 0),
+    ("XOF", "F CFA", 0),
+    ("MAD", "MAD", 2),
+];
+
+/// ISO 4217 currency, the ones not listed above are written with their code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Currency {
+    pub code: String,
+    pub symbol: String,
+    pub decimals: usize,
+}
+
+impl FromStr for Currency {
+    type Err = Errcode;
+
+    fn from_str(s: &str) -> Result<Currency, Errcode> {
+        let code = s.trim().to_ascii_uppercase();
+        if (code.len() != 3) || !code.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(Errcode::InvalidCurrency(s.to_string()));
+        }
+        let (symbol, decimals) = KNOWN_CURRENCIES
+            .iter()
+            .find(|(c, ..)| *c == code)
+            .map(|(_, sym, dec)| (sym.to_string(), *dec))
+            .unwrap_or_else(|| (code.clone(), 2));
+        Ok(Currency {
+            code,
+            symbol,
+            decimals,
+        })
+    }
+}
+
+/// How numbers are written in the language of the documents
+#[derive(Debug, Clone)]
+pub struct Locale {
+    decimal_sep: String,
+    grouping_sep: String,
+    symbol_before: bool,
+    symbol_sep: String,
+    negative_format: String,
+}
+
+impl Locale {
+    pub fn from_lang(lang: &LangDict) -> Result<Locale, Errcode> {
+        let word = |w| lang.get_doctype_word("locale", w);
+        let symbol_before = match word("symbol_position").as_str() {
+            "before" => true,
+            "after" => false,
+            p => {
+                return Err(Errcode::InvalidConfig(
+                    "locale",
+                    format!("Unknown symbol position {p:?}"),
+                ))
+            }
+        };
+        let negative_format = word("negative_format");
+        if !negative_format.contains("{amount}") {
+            return Err(Errcode::InvalidConfig(
+                "locale",
+                format!("Negative format {negative_format:?} lacks the {{amount}} token"),
+            ));
+        }
+        Ok(Locale {
+            decimal_sep: word("decimal_separator"),
+            grouping_sep: word("grouping_separator"),
+            symbol_before,
+            symbol_sep: word("symbol_separator"),
+            negative_format,
+        })
+    }
+
+    fn digits(&self, value: f64, decimals: usize) -> String {
+        let digits = format!("{:.*}", decimals, value.abs());
+        let (int_part, dec_part) = match digits.split_once('.') {
+            Some((int_part, dec_part)) => (int_part, Some(dec_part)),
+            None => (digits.as_str(), None),
+        };
+        let mut res = String::new();
+        for (n, c) in int_part.chars().enumerate() {
+            if (n > 0) && ((int_part.len() - n) % 3 == 0) {
+                res += &self.grouping_sep;
+            }
+            res.push(c);
+        }
+        if let Some(dec_part) = dec_part {
+            res += &self.decimal_sep;
+            res += dec_part;
+        }
+        res
+    }
+
+    /// Writes `value` with `amount`, the absolute value of the number, adding the sign if
+    /// the value is still negative once rounded
+    fn signed(&self, value: f64, decimals: usize, amount: String) -> String {
+        let rounded_zero = format!("{:.*}", decimals, value.abs())
+            .chars()
+            .all(|c| !c.is_ascii_digit() || (c == '0'));
+        if (value < 0.0) && !rounded_zero {
+            self.negative_format.replace("{amount}", &amount)
+        } else {
+            amount
+        }
+    }
+
+    /// Any number, with a fixed number of decimals
+    pub fn decimal(&self, value: f64, decimals: usize) -> String {
+        self.signed(value, decimals, self.digits(value, decimals))
+    }
+
+    /// Quantities, with only the decimals needed
+    pub fn quantity(&self, value: f64) -> String {
+        let decimals = (0..3)
+            .find(|d| {
+                let factor = 10f64.powi(*d);
+                ((value * factor).round() - value * factor).abs() < 1e-9
+            })
+            .unwrap_or(3) as usize;
+        self.decimal(value, decimals)
+    }
+}
+
+/// How amounts of a document are written
+pub struct AmountFormat {
+    pub currency: Currency,
+    pub locale: Locale,
+}
+
+impl AmountFormat {
+    pub fn new(lang: &LangDict, currency: &str) -> Result<AmountFormat, Errcode> {
+        Ok(AmountFormat {
+            currency: currency.parse()?,
+            locale: Locale::from_lang(lang)?,
+        })
+    }
+
+    /// The single function used to write the amounts, in documents and in the terminal
+    pub fn format(&self, amount: f64) -> String {
+        let digits = self.locale.digits(amount, self.currency.decimals);
+        let symbol = &self.currency.symbol;
+        let sep = &self.locale.symbol_sep;
+        let with_symbol = if self.locale.symbol_before {
+            format!("{symbol}{sep}{digits}")
+        } else {
+            format!("{digits}{sep}{symbol}")
+        };
+        self.locale
+            .signed(amount, self.currency.decimals, with_symbol)
+    }
+}
+
+#[test]
+fn currency_formatting() {
+    let locale = |decimal: &str, grouping: &str, before: bool, sep: &str, neg: &str| Locale {
+        decimal_sep: decimal.to_string(),
+        grouping_sep: grouping.to_string(),
+        symbol_before: before,
+        symbol_sep: sep.to_string(),
+        negative_format: neg.to_string(),
+    };
+    let fr = locale(",", " ", false, " ", "-{amount}");
+    let us = locale(".", ",", true, "", "({amount})");
+    let fmt = |code: &str, locale: &Locale| AmountFormat {
+        currency: code.parse().unwrap(),
+        locale: locale.clone(),
+    };
+    assert_eq!(fmt("eur", &fr).format(1234567.891), "1 234 567,89 €");
+    assert_eq!(fmt("EUR", &fr).format(-1234.5), "-1 234,50 €");
+    assert_eq!(fmt("USD", &us).format(-1234.5), "($1,234.50)");
+    assert_eq!(fmt("JPY", &us).format(999.6), "¥1,000");
+    assert_eq!(fmt("EUR", &us).format(-0.001), "€0.00");
+    assert_eq!(fmt("BRL", &fr).format(12.0), "12,00 BRL");
+    assert_eq!(fr.quantity(2.0), "2");
+    assert_eq!(fr.quantity(1.25), "1,25");
+    assert_eq!(us.decimal(-20.0, 2), "(20.00)");
+    assert!("EURO".parse::<Currency>().is_err());
+    assert!("E1R".parse::<Currency>().is_err());
+}
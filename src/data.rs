@@ -1,40 +1,206 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::contact::ContactBook;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditEvent;
+use crate::contact::{Contact, ContactBook};
 use crate::doctype::invoice::InvoiceSavedData;
 use crate::doctype::quotation::QuotationSavedData;
-use crate::doctype::DocumentType;
 use crate::errors::Errcode;
+use crate::lang::LangDict;
+use crate::rates::RateTable;
+use crate::storage::Storage;
+
+/// Date of a document, saved as ISO 8601. The dates entered as free text before they were
+/// parsed are kept as they were, when they could not be understood.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Date {
+    Day(NaiveDate),
+    Text(String),
+}
+
+impl Date {
+    pub fn localized(&self, lang: &LangDict) -> Result<String, Errcode> {
+        match self {
+            Date::Day(date) => lang.format_date(date),
+            Date::Text(text) => Ok(text.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Date::Day(date) => write!(f, "{date}"),
+            Date::Text(text) => write!(f, "{text}"),
+        }
+    }
+}
 
-pub type Date = String;
 pub type Transaction = (String, f64, f64);
 
 pub struct Datastore {
     pub contacts: ContactBook,
     pub invoices: InvoiceSavedData,
     pub quotations: QuotationSavedData,
+    /// Changes made during this run, to write in the audit log once saved
+    pub audit: Vec<AuditEvent>,
+    /// Exchange rates imported, kept apart from the documents
+    pub rates: RateTable,
 }
 
 impl Datastore {
-    pub fn import(root: &Path) -> Datastore {
-        if !root.exists() {
-            std::fs::create_dir(root).expect("Unable to create data directory");
-        }
-
-        let contacts = ContactBook::import(root);
-        let invoices = InvoiceSavedData::import(&DocumentType::Invoice.fname(root));
-        let quotes = QuotationSavedData::import(&DocumentType::Quotation.fname(root));
-        Datastore {
+    pub fn import(store: &dyn Storage) -> Result<Datastore, Errcode> {
+        let contacts = store.load_contacts()?;
+        let invoices = store.load_invoices()?;
+        let quotes = store.load_quotations()?;
+        Ok(Datastore {
             contacts,
             invoices,
             quotations: quotes,
+            audit: vec![],
+            rates: RateTable::default(),
+        })
+    }
+
+    pub fn get_or_add_contact(&mut self, slug: &String) -> Contact {
+        if !self.contacts.contains(slug) {
+            self.audit
+                .push(AuditEvent::ContactAdded { slug: slug.clone() });
+        }
+        self.contacts.get_or_add(slug)
+    }
+
+    /// True if no contact nor document was ever added
+    pub fn is_empty(&self) -> bool {
+        self.contacts.iter().next().is_none()
+            && self.invoices.history.is_empty()
+            && self.quotations.history.is_empty()
+    }
+
+    /// Saves all the data at once, either everything is updated or nothing is
+    pub fn export(&self, store: &mut dyn Storage) -> Result<(), Errcode> {
+        store.save(self)
+    }
+
+    pub fn as_json(&self) -> Result<serde_json::Value, Errcode> {
+        let mut map = serde_json::Map::new();
+        map.insert(
+            "contacts".to_string(),
+            serde_json::to_value(&self.contacts)?,
+        );
+        map.insert(
+            "invoices".to_string(),
+            serde_json::to_value(&self.invoices)?,
+        );
+        map.insert(
+            "quotations".to_string(),
+            serde_json::to_value(&self.quotations)?,
+        );
+        Ok(serde_json::Value::Object(map))
+    }
+}
+
+fn tmp_path(dest: &Path) -> PathBuf {
+    let mut fname = dest.file_name().unwrap_or_default().to_os_string();
+    fname.push(".tmp");
+    dest.with_file_name(fname)
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> Result<(), Errcode> {
+    use std::io::Write;
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Replaces the file content by writing to a temporary file then renaming it over the
+/// destination, so the file is never left half-written.
+pub fn write_atomic(dest: &Path, content: &[u8]) -> Result<(), Errcode> {
+    let tmp = tmp_path(dest);
+    if let Err(e) = write_synced(&tmp, content) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    if let Err(e) = std::fs::rename(&tmp, dest) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Atomically replaces several files together.
+/// If anything fails, the files that were already replaced get their previous content back.
+pub fn commit_files(files: Vec<(PathBuf, String)>) -> Result<(), Errcode> {
+    let mut staged: Vec<(PathBuf, PathBuf, Option<Vec<u8>>)> = vec![];
+    for (dest, content) in files {
+        let tmp = tmp_path(&dest);
+        let previous = if dest.is_file() {
+            std::fs::read(&dest).map(Some).map_err(Errcode::from)
+        } else {
+            Ok(None)
+        };
+        let res = previous.and_then(|prev| write_synced(&tmp, content.as_bytes()).map(|_| prev));
+        match res {
+            Ok(prev) => staged.push((dest, tmp, prev)),
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp);
+                for (_, tmp, _) in staged {
+                    let _ = std::fs::remove_file(tmp);
+                }
+                return Err(e);
+            }
         }
     }
 
-    pub fn export(&self, root: &Path) -> Result<(), Errcode> {
-        DocumentType::Invoice.export_data(root, &self.invoices)?;
-        DocumentType::Quotation.export_data(root, &self.quotations)?;
-        self.contacts.export(root)?;
-        Ok(())
+    let mut committed: Vec<(PathBuf, Option<Vec<u8>>)> = vec![];
+    let mut staged = staged.into_iter();
+    while let Some((dest, tmp, prev)) = staged.next() {
+        if let Err(e) = std::fs::rename(&tmp, &dest) {
+            let _ = std::fs::remove_file(&tmp);
+            for (_, tmp, _) in staged {
+                let _ = std::fs::remove_file(tmp);
+            }
+            rollback(committed);
+            return Err(e.into());
+        }
+        committed.push((dest, prev));
+    }
+    Ok(())
+}
+
+fn rollback(committed: Vec<(PathBuf, Option<Vec<u8>>)>) {
+    for (dest, prev) in committed.into_iter().rev() {
+        let res = match prev {
+            Some(content) => write_atomic(&dest, &content),
+            None => std::fs::remove_file(&dest).map_err(Errcode::from),
+        };
+        if let Err(e) = res {
+            println!("Unable to restore {dest:?} after a failed write: {e}");
+        }
     }
 }
+
+#[test]
+fn commit_files_rollback() {
+    let dir = std::env::temp_dir().join(format!("docgen_commit_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("blocking_dir")).unwrap();
+    std::fs::write(dir.join("a.json"), "old").unwrap();
+
+    // Renaming over a directory fails, the first file must get its old content back
+    let res = commit_files(vec![
+        (dir.join("a.json"), "new".to_string()),
+        (dir.join("blocking_dir"), "new".to_string()),
+    ]);
+    assert!(res.is_err());
+    assert_eq!(std::fs::read_to_string(dir.join("a.json")).unwrap(), "old");
+    assert!(!dir.join("a.json.tmp").exists());
+    assert!(!dir.join("blocking_dir.tmp").exists());
+
+    commit_files(vec![(dir.join("a.json"), "new".to_string())]).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("a.json")).unwrap(), "new");
+    std::fs::remove_dir_all(dir).unwrap();
+}
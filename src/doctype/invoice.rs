@@ -1,27 +1,43 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::PathBuf;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Days, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::audit::AuditEvent;
 use crate::codegen::{
     generate_header, generate_iban, generate_summary_table, generate_transaction_table,
     write_page_settings,
 };
 use crate::config::ConfigStore;
-use crate::contact::Contact;
+use crate::currency::AmountFormat;
 use crate::data::{Datastore, Date};
 use crate::errors::Errcode;
-use crate::interface::ask::{ask_for_transactions, ask_user_nonempty};
+use crate::filename::{file_name, FileNameFields};
+use crate::interface::ask::{ask_currency, ask_date, ask_for_transactions};
 use crate::interface::select_from_list;
-use crate::lang::LangDict;
+use crate::lang::{LangDict, Languages};
+use crate::numbering::{Counters, NumberingScheme};
+use crate::payment_qr::{epc_payload, qr_code_svg, EPC_QR_ASSET};
+use crate::qr_bill::{generate_qr_bill, QrBill};
+use crate::rates::{Conversion, ExchangeRate};
 
 use crate::doctype::quotation::QuotationInput;
-use crate::doctype::TypstData;
+use crate::doctype::snapshot::DocumentSnapshot;
+use crate::doctype::{
+    candidate, default_currency, exchange_rate, profile_config, render_snapshot, select_recipient,
+    Assets, DocumentKey, DocumentType, TypstData,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct InvoiceSavedData {
     pub history: Vec<InvoiceInput>,
     pub id_counter: usize,
+    #[serde(default)]
+    pub counters: Counters,
+    /// Numbering sequences of the other issuer profiles
+    #[serde(default)]
+    pub profile_counters: HashMap<String, Counters>,
 }
 
 impl InvoiceSavedData {
@@ -29,47 +45,174 @@ impl InvoiceSavedData {
         InvoiceSavedData {
             id_counter: 1,
             history: vec![],
+            counters: HashMap::new(),
+            profile_counters: HashMap::new(),
         }
     }
 
-    pub fn import(fname: &Path) -> InvoiceSavedData {
-        if !fname.is_file() {
-            return InvoiceSavedData::init();
-        }
+    /// Finds an invoice from its number, or its internal id, among the invoices of the
+    /// issuer profile if set
+    pub fn find(
+        &self,
+        cfg: &ConfigStore,
+        key: &DocumentKey,
+        profile: Option<&str>,
+    ) -> Result<&InvoiceInput, Errcode> {
+        let found = self
+            .history
+            .iter()
+            .filter(|inp| profile.is_none() || (inp.profile.as_deref() == profile))
+            .map(|inp| (inp, inp.display_number(cfg)))
+            .filter(|(inp, number)| key.matches(inp.id, number))
+            .map(|(inp, number)| (inp, candidate(&number, inp.id, &inp.profile)))
+            .collect();
+        key.single(found)
+    }
 
-        let json_str =
-            std::fs::read_to_string(fname).expect("Unable to read JSON data from {fname}");
-        match serde_json::from_str::<Self>(json_str.as_str()) {
-            Ok(d) => d,
-            Err(_) => {
-                println!("Failed to import the invoice data");
-                InvoiceSavedData::init()
-            }
+    /// Takes the next number of the issuer profile
+    pub fn allocate_number(
+        &mut self,
+        cfg: &ConfigStore,
+        profile: &Option<String>,
+        id: usize,
+    ) -> Result<String, Errcode> {
+        let scheme = NumberingScheme::from_config(cfg, "invoice")?;
+        Ok(match profile {
+            Some(p) => scheme.allocate(
+                self.profile_counters.entry(p.clone()).or_default(),
+                None,
+                &Utc::now(),
+            ),
+            None => scheme.allocate(&mut self.counters, Some(id), &Utc::now()),
+        })
+    }
+
+    /// Adds a payment to an invoice, returns the amount left to pay. Negative
+    /// amounts are refunds, zero or non-finite ones are refused
+    pub fn record_payment(
+        &mut self,
+        cfg: &ConfigStore,
+        key: &DocumentKey,
+        profile: Option<&str>,
+        payment: Payment,
+    ) -> Result<(usize, f64), Errcode> {
+        if !payment.amount.is_finite() || payment.amount.abs() < 0.005 {
+            return Err(Errcode::InvalidAmount(payment.amount));
         }
+        let id = self.find(cfg, key, profile)?.id;
+        let inp = self.history.iter_mut().find(|inp| inp.id == id).unwrap();
+        inp.payments.push(payment);
+        Ok((id, inp.balance(None)))
     }
 }
 
+/// Payment received for an invoice, in the currency of the invoice
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Payment {
+    pub date: NaiveDate,
+    pub amount: f64,
+    /// Reference of the transfer, or how it was paid
+    #[serde(default)]
+    pub reference: Option<String>,
+    /// Identifier of the line of the bank statement it was imported from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bank_id: Option<String>,
+}
+
+/// Day the payment of an invoice created on `created` is due
+fn due_date(cfg: &ConfigStore, created: NaiveDate) -> NaiveDate {
+    created + Days::new(cfg.get_int("invoice", "payment_days").max(0) as u64)
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct InvoiceInput {
     pub id: usize,
     pub recipient: String,
     pub quote_nb: Option<usize>,
-    date_sell: Date,
-    tx: Vec<(String, f64, f64)>,
-    tax_rate: Option<f64>,
-    created: String,
+    pub date_sell: Date,
+    pub tx: Vec<(String, f64, f64)>,
+    pub tax_rate: Option<f64>,
+    pub created: Date,
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub snapshot: Option<DocumentSnapshot>,
+    #[serde(default)]
+    number: Option<String>,
+    #[serde(default)]
+    quote_number: Option<String>,
+    /// Issuer profile, none for the main one
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// ISO 4217 code, none for the documents saved before currencies were supported
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Rate to the accounting currency, for the documents written in another one
+    #[serde(default)]
+    pub exchange_rate: Option<ExchangeRate>,
+    /// Language the document is written in, none for the default one
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// None for the invoices saved before the payment terms existed
+    #[serde(default)]
+    pub due_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub payments: Vec<Payment>,
 }
 
 impl InvoiceInput {
+    pub fn total_no_tax(&self) -> f64 {
+        self.tx.iter().map(|(_, units, ppu)| units * ppu).sum()
+    }
+
+    pub fn total_with_tax(&self) -> f64 {
+        let total = self.total_no_tax();
+        total + total * self.tax_rate.unwrap_or(0.0)
+    }
+
+    /// Amount left to pay, counting the payments received until `day` if set
+    pub fn balance(&self, day: Option<NaiveDate>) -> f64 {
+        let paid: f64 = self
+            .payments
+            .iter()
+            .filter(|p| day.is_none_or(|day| p.date <= day))
+            .map(|p| p.amount)
+            .sum();
+        self.total_with_tax() - paid
+    }
+
+    /// Invoices saved without a due date get the payment terms of the configuration
+    pub fn payment_due(&self, cfg: &ConfigStore) -> Option<NaiveDate> {
+        match (self.due_date, &self.created) {
+            (Some(due), _) => Some(due),
+            (None, Date::Day(created)) => Some(due_date(cfg, *created)),
+            (None, Date::Text(_)) => None,
+        }
+    }
+
+    /// Invoices saved before the numbering schemes existed only have their id
+    pub fn display_number(&self, cfg: &ConfigStore) -> String {
+        self.number
+            .clone()
+            .unwrap_or_else(|| format!("{}{:0>5}", cfg.get_str("invoice", "id_prefix"), self.id))
+    }
+
+    pub fn currency_code(&self, cfg: &ConfigStore) -> String {
+        self.currency
+            .clone()
+            .unwrap_or_else(|| cfg.get_str("currency", "default").to_string())
+    }
+
     pub fn from_quote(
         id: usize,
+        number: String,
         config: &ConfigStore,
         lang: &LangDict,
         idx: usize,
         quote: &QuotationInput,
     ) -> InvoiceInput {
         let current_date = Utc::now();
-        let created = lang.get_date_fmt(&current_date);
+        let created = Date::Day(current_date.date_naive());
         let tax_rate = if config.get_bool("taxes", "tax_applicable") {
             Some(config.get_float("taxes", "tax_rate"))
         } else {
@@ -79,21 +222,34 @@ impl InvoiceInput {
             id,
             recipient: quote.recipient.clone(),
             tx: quote.tx.clone(),
-            date_sell: ask_user_nonempty("Enter the date where the sell was done: "),
+            date_sell: Date::Day(ask_date("Enter the date where the sell was done: ", lang)),
             quote_nb: Some(idx),
             tax_rate,
             created,
+            created_at: Some(current_date),
+            snapshot: None,
+            number: Some(number),
+            quote_number: Some(quote.display_number(config)),
+            profile: quote.profile.clone(),
+            currency: Some(quote.currency_code(config)),
+            exchange_rate: None,
+            lang: None,
+            due_date: Some(due_date(config, current_date.date_naive())),
+            payments: vec![],
         }
     }
     pub fn ask(
         id: usize,
+        number: String,
         recipient: String,
+        profile: Option<String>,
+        currency: String,
         config: &ConfigStore,
         lang: &LangDict,
     ) -> InvoiceInput {
         let current_date = Utc::now();
-        let created = lang.get_date_fmt(&current_date);
-        let date_sell = ask_user_nonempty("Enter the date where the sell was done: ");
+        let created = Date::Day(current_date.date_naive());
+        let date_sell = Date::Day(ask_date("Enter the date where the sell was done: ", lang));
 
         let tx = ask_for_transactions(lang);
         let tax_rate = if config.get_bool("taxes", "tax_applicable") {
@@ -110,6 +266,16 @@ impl InvoiceInput {
             tx,
             tax_rate,
             created,
+            created_at: Some(current_date),
+            snapshot: None,
+            number: Some(number),
+            quote_number: None,
+            profile,
+            currency: Some(currency),
+            exchange_rate: None,
+            lang: None,
+            due_date: Some(due_date(config, current_date.date_naive())),
+            payments: vec![],
         }
     }
 }
@@ -117,47 +283,114 @@ impl InvoiceInput {
 pub struct InvoiceBuilder<'a> {
     cfg: &'a ConfigStore,
     lang: &'a LangDict,
-    data: &'a mut Datastore,
+    snap: &'a DocumentSnapshot,
     inp: &'a InvoiceInput,
 }
 
 impl<'a> InvoiceBuilder<'a> {
-    pub fn generate_invoice(&mut self) -> Result<(String, String), Errcode> {
-        // Getting necessary data before writing the code
-        self.data.invoices.history.push(self.inp.clone());
-        let current_date = Utc::now();
-
-        let fname = format!(
-            "invoice_{}_{}_{}.pdf",
-            self.inp.recipient,
-            self.data.invoices.history.len(),
-            current_date.format("%d%m%y"),
-        );
+    /// Path and code of the invoice, with the assets generated for it
+    pub fn generate_invoice(&self) -> Result<(PathBuf, String, Assets), Errcode> {
+        // Invoices saved before the creation date was stored are named after the current date
+        let fields = FileNameFields {
+            doctype: "invoice",
+            number: &self.inp.display_number(self.cfg),
+            recipient: &self.inp.recipient,
+            recipient_name: &self.snap.recipient.name,
+            status: "issued",
+            created_at: self.inp.created_at.unwrap_or_else(Utc::now),
+        };
+        let fname = file_name(&self.snap.layout.filename, &fields)?;
 
-        let footer = self.cfg.get_str("invoice", "footer");
         let mut source = "".to_string();
-        write_page_settings(&mut source, footer);
-        generate_header(self.cfg, &mut source);
+        write_page_settings(&mut source, &self.snap.layout.footer);
+        generate_header(&self.snap.issuer, &mut source);
         source += "#v(sep_par())\n";
-        self.generate_metadata(&mut source, &current_date);
+        self.generate_metadata(&mut source)?;
         source += "#v(sep_par())\n";
-        let total_price = generate_transaction_table(&mut source, &self.inp.tx, self.lang);
+        let amounts = AmountFormat::new(self.lang, &self.inp.currency_code(self.cfg))?;
+        let conversion = match self.inp.exchange_rate.as_ref() {
+            Some(rate) => Some(Conversion {
+                rate,
+                amounts: AmountFormat::new(self.lang, &rate.currency)?,
+            }),
+            None => None,
+        };
+        let total_price =
+            generate_transaction_table(&mut source, &self.inp.tx, self.lang, &amounts);
         source += "#v(sep_par())\n";
-        generate_summary_table(&mut source, total_price, self.lang, self.cfg);
+        generate_summary_table(
+            &mut source,
+            total_price,
+            self.lang,
+            &self.snap.taxes,
+            &amounts,
+            conversion.as_ref(),
+        );
         source += "#v(sep_par())\n";
 
-        if self.cfg.get_bool("invoice", "add_iban") {
-            generate_iban(&mut source, self.lang, self.cfg);
+        let mut assets = vec![];
+        if self.snap.layout.add_iban {
+            let qr_code = self.payment_qr_code()?;
+            let qr_path = qr_code.as_ref().map(|_| EPC_QR_ASSET);
+            generate_iban(&mut source, self.lang, &self.snap.bank, qr_path);
+            assets.extend(qr_code);
+        }
+        if let Some(bill) = self.qr_bill()? {
+            generate_qr_bill(&mut source, self.lang, &bill);
+            assets.push(bill.qr_code_svg()?);
         }
 
         source += "\n";
-        Ok((fname, source))
+        Ok((fname, source, assets))
+    }
+
+    /// QR code of the SEPA transfer paying the invoice, if enabled
+    fn payment_qr_code(&self) -> Result<Option<(PathBuf, Vec<u8>)>, Errcode> {
+        if !self.snap.layout.add_payment_qr {
+            return Ok(None);
+        }
+        let payload = epc_payload(
+            &self.snap.issuer.name,
+            &self.snap.bank,
+            self.inp.total_with_tax(),
+            &self.inp.currency_code(self.cfg),
+            &self.inp.display_number(self.cfg),
+        );
+        match payload {
+            Some(payload) => Ok(Some(qr_code_svg(&payload)?)),
+            None => {
+                println!("[*] No payment QR code, only the invoices in euros can be paid with it");
+                Ok(None)
+            }
+        }
     }
 
-    fn generate_metadata(&self, source: &mut String, current_date: &DateTime<Utc>) {
-        let current_date_fmt = self.lang.get_date_fmt(current_date);
+    /// Swiss QR-bill of the invoice, if enabled
+    fn qr_bill(&self) -> Result<Option<QrBill>, Errcode> {
+        let Some(settings) = self.snap.layout.qr_bill.as_ref() else {
+            return Ok(None);
+        };
+        let bill = QrBill::new(
+            settings,
+            &self.snap.issuer.name,
+            &self.snap.bank.iban,
+            (self.inp.id, &self.inp.display_number(self.cfg)),
+            self.inp.total_with_tax(),
+            &self.inp.currency_code(self.cfg),
+        )?;
+        if bill.is_none() {
+            println!("[*] No QR-bill, the invoice is not in CHF or EUR or has nothing to pay");
+        }
+        Ok(bill)
+    }
 
-        let quotation_md = if let Some(nb) = self.inp.quote_nb {
+    fn generate_metadata(&self, source: &mut String) -> Result<(), Errcode> {
+        let quotation_md = if let Some(nb) = self.inp.quote_number.as_ref() {
+            format!(
+                "\\\n\t{} \\#*{nb}*",
+                self.lang.get_doctype_word("invoice", "quotation_related"),
+            )
+        } else if let Some(nb) = self.inp.quote_nb {
             format!(
                 "\\\n\t{} \\#*{}{:0>5}*",
                 self.lang.get_doctype_word("invoice", "quotation_related"),
@@ -177,66 +410,191 @@ impl<'a> InvoiceBuilder<'a> {
                 {} \\ {} \\
             ],
             align(right)[
-                {} \\#*{}{:0>5}* \\
+                {} \\#*{}* \\
                 {} *{}* \\
                 {}: *{}* {quotation_md}
             ],
         )",
             self.lang.get_doctype_word("invoice", "recipient_intro"),
-            self.data.contacts.get(&self.inp.recipient).name,
-            self.data.contacts.get(&self.inp.recipient).address,
+            self.snap.recipient.name,
+            self.snap.recipient.address,
             self.lang.get_doctype_word("invoice", "invoice_nb"),
-            self.cfg.get_str("invoice", "id_prefix"),
-            self.data.invoices.history.len(),
+            self.inp.display_number(self.cfg),
             self.lang.get_doctype_word("general", "creation_date"),
-            current_date_fmt,
+            self.inp.created.localized(self.lang)?,
             self.lang.get_doctype_word("general", "sell_date"),
-            self.inp.date_sell,
+            self.inp.date_sell.localized(self.lang)?,
         )
         .as_str();
+        Ok(())
     }
 }
 
 pub fn generate(
     cfg: &ConfigStore,
-    lang: &LangDict,
+    langs: &Languages,
     data: &mut Datastore,
+    profile: Option<&str>,
 ) -> Result<TypstData, Errcode> {
+    let (recipient, profile, cfg) = select_recipient(cfg, data, profile)?;
+    let cfg = &cfg;
+    let (lang_code, lang) = langs.select(cfg, &recipient.lang)?;
+    let lang = &lang;
+    let slug = recipient.slug.clone();
     let id = data.invoices.id_counter;
     data.invoices.id_counter += 1;
-
-    let slug = Contact::ask_slug();
-    let recipient = data.contacts.get_or_add(&slug);
+    let number = data.invoices.allocate_number(cfg, &profile, id)?;
     data.contacts.get_mut(&slug).invoices.push(id);
 
-    let inp = if let Some(qhist) = data.quotations.history.get(&slug) {
-        let qhist = qhist
-            .iter()
-            .enumerate()
-            .filter(|(_, (_, i))| i.is_none())
-            .collect::<Vec<(usize, &(QuotationInput, Option<usize>))>>();
-        let filtered_idx = select_from_list(&qhist, |(_, (inp, _))| inp.single_line_display());
+    // Only the quotations of the same issuer profile can be invoiced
+    let qhist = data
+        .quotations
+        .history
+        .get(&slug)
+        .map(|qhist| {
+            qhist
+                .iter()
+                .enumerate()
+                .filter(|(_, (inp, i))| i.is_none() && (inp.profile == profile))
+                .collect::<Vec<(usize, &(QuotationInput, Option<usize>))>>()
+        })
+        .unwrap_or_default();
+    // The rate is needed before the details of the invoice are typed in
+    let mut inp = if !qhist.is_empty() {
+        let filtered_idx =
+            select_from_list(&qhist, |(_, (inp, _))| inp.single_line_display(cfg, lang));
         let idx = qhist.get(filtered_idx).unwrap().0;
         let quote = &qhist.get(filtered_idx).unwrap().1 .0;
-        InvoiceInput::from_quote(id, cfg, lang, idx, quote)
+        let rate = exchange_rate(cfg, data, &quote.currency_code(cfg))?;
+        let mut inp = InvoiceInput::from_quote(id, number, cfg, lang, idx, quote);
+        inp.exchange_rate = rate;
+        inp
     } else {
-        InvoiceInput::ask(id, recipient.slug, cfg, lang)
+        let currency = ask_currency(&default_currency(cfg, &recipient));
+        let rate = exchange_rate(cfg, data, &currency)?;
+        let mut inp = InvoiceInput::ask(id, number, slug, profile, currency, cfg, lang);
+        inp.exchange_rate = rate;
+        inp
     };
-
-    let mut builder = InvoiceBuilder {
+    inp.lang = Some(lang_code);
+    let snapshot = DocumentSnapshot::take(cfg, DocumentType::Invoice, &recipient);
+    let builder = InvoiceBuilder {
         cfg,
         lang,
-        data,
+        snap: &snapshot,
         inp: &inp,
     };
-    let (fname, result) = builder.generate_invoice()?;
-    // For debug
-    std::fs::write("/tmp/.typst_result.typ", &result)?;
+    let (fname, result, assets) = builder.generate_invoice()?;
 
+    data.audit.push(AuditEvent::InvoiceCreated {
+        id: inp.id,
+        number: inp.number.clone(),
+        recipient: inp.recipient.clone(),
+        total_no_tax: inp.total_no_tax(),
+        currency: inp.currency.clone(),
+    });
     if let Some(quote_nb) = inp.quote_nb {
-        let invoice_nb = data.invoices.history.len();
         data.quotations
-            .mark_quotation_finished(&inp.recipient, quote_nb, invoice_nb)?;
+            .mark_quotation_finished(&inp.recipient, quote_nb, inp.id)?;
+        let quotation = data.quotations.history.get(&inp.recipient).unwrap()[quote_nb]
+            .0
+            .id;
+        data.audit.push(AuditEvent::QuotationInvoiced {
+            quotation,
+            invoice: inp.id,
+        });
+    }
+    inp.snapshot = Some(snapshot);
+    data.invoices.history.push(inp);
+    let mut typst_data = TypstData::new(fname, result, cfg)?;
+    typst_data.assets = assets;
+    Ok(typst_data)
+}
+
+pub fn render(
+    cfg: &ConfigStore,
+    langs: &Languages,
+    data: &Datastore,
+    key: &DocumentKey,
+    profile: Option<&str>,
+    use_current_cfg: bool,
+) -> Result<TypstData, Errcode> {
+    let inp = data.invoices.find(cfg, key, profile)?;
+    let cfg = &profile_config(cfg, &inp.profile)?;
+    let snap = render_snapshot(
+        cfg,
+        data,
+        DocumentType::Invoice,
+        inp.snapshot.as_ref(),
+        &inp.recipient,
+        use_current_cfg,
+    );
+    let (_, lang) = langs.select(cfg, &inp.lang)?;
+    let lang = &lang;
+    let builder = InvoiceBuilder {
+        cfg,
+        lang,
+        snap: &snap,
+        inp,
+    };
+    let (fname, result, assets) = builder.generate_invoice()?;
+    let mut typst_data = TypstData::new(fname, result, cfg)?;
+    typst_data.assets = assets;
+    Ok(typst_data)
+}
+
+#[test]
+fn find_invoices() {
+    let cfg: ConfigStore = toml::from_str(include_str!("../../default/config.toml")).unwrap();
+    let invoices: InvoiceSavedData = serde_json::from_str(
+        r#"{"id_counter": 4, "history": [
+        {"id": 1, "recipient": "acme", "quote_nb": null, "date_sell": "2026-01-10",
+         "tx": [], "tax_rate": null, "created": "2026-01-12", "number": "F00001"},
+        {"id": 2, "recipient": "acme", "quote_nb": null, "date_sell": "2026-01-10",
+         "tx": [], "tax_rate": null, "created": "2026-01-12", "number": "F00001",
+         "profile": "freelance"},
+        {"id": 3, "recipient": "acme", "quote_nb": null, "date_sell": "2026-01-10",
+         "tx": [], "tax_rate": null, "created": "2026-01-12", "number": "2"}]}"#,
+    )
+    .unwrap();
+    let number = |n: &str| DocumentKey::Number(n.to_string());
+    let Err(Errcode::DocumentAmbiguous(_, candidates)) =
+        invoices.find(&cfg, &number("F00001"), None)
+    else {
+        panic!("Invoices of several profiles with the same number must not be mixed up");
+    };
+    assert_eq!(
+        candidates,
+        vec!["F00001 (id 1)", "F00001 (id 2, profile freelance)"]
+    );
+    let found = invoices.find(&cfg, &number("F00001"), Some("freelance"));
+    assert_eq!(found.unwrap().id, 2);
+    // Numbers are never taken for ids
+    assert_eq!(invoices.find(&cfg, &number("2"), None).unwrap().id, 3);
+    assert_eq!(
+        invoices.find(&cfg, &DocumentKey::Id(2), None).unwrap().id,
+        2
+    );
+    assert!(matches!(
+        invoices.find(&cfg, &number("3"), None),
+        Err(Errcode::DocumentNotFound(_))
+    ));
+
+    let mut invoices = invoices;
+    let payment = |amount| Payment {
+        date: NaiveDate::from_ymd_opt(2026, 2, 1).unwrap(),
+        amount,
+        reference: None,
+        bank_id: None,
+    };
+    for amount in [0.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(
+            invoices.record_payment(&cfg, &number("2"), None, payment(amount)),
+            Err(Errcode::InvalidAmount(_))
+        ));
     }
-    Ok(TypstData::new(fname, result))
+    let (id, _) = invoices
+        .record_payment(&cfg, &number("2"), None, payment(-10.0))
+        .unwrap();
+    assert_eq!(id, 3);
 }
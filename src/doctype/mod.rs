@@ -1,23 +1,103 @@
 use std::path::{Path, PathBuf};
 
-use serde::Serialize;
+use chrono::Utc;
 
 use crate::config::ConfigStore;
+use crate::contact::Contact;
 use crate::data::Datastore;
 use crate::errors::Errcode;
-use crate::lang::LangDict;
+use crate::filename::CollisionPolicy;
+use crate::lang::Languages;
+use crate::rates::ExchangeRate;
+use crate::style::Style;
 
 pub mod invoice;
 pub mod quotation;
+pub mod snapshot;
+pub mod statement;
+
+use snapshot::DocumentSnapshot;
+
+/// Files generated with a document, by their path in the assets
+pub type Assets = Vec<(PathBuf, Vec<u8>)>;
 
 pub struct TypstData {
-    pub fname: String,
+    /// Path of the document, relative to the output directory
+    pub fname: PathBuf,
     pub code: String,
+    /// Style settings of the issuer profile
+    pub style: Style,
+    /// Files generated with the document, added to the assets before compiling it
+    pub assets: Assets,
+    /// What to do when the file exists, as set for the issuer profile
+    pub collision: CollisionPolicy,
+}
+
+/// Document designated on the command line, by its number or by its internal id
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DocumentKey {
+    Number(String),
+    Id(usize),
+}
+
+impl DocumentKey {
+    pub fn from_arg(number: &str, is_id: bool) -> Result<DocumentKey, Errcode> {
+        if !is_id {
+            return Ok(DocumentKey::Number(number.to_string()));
+        }
+        number
+            .parse()
+            .map(DocumentKey::Id)
+            .map_err(|_| Errcode::InvalidConfig("id", format!("{number:?} is not a document id")))
+    }
+
+    pub fn matches(&self, id: usize, number: &str) -> bool {
+        match self {
+            DocumentKey::Number(n) => n == number,
+            DocumentKey::Id(i) => *i == id,
+        }
+    }
+
+    /// The only document matching the key, each one given with its description
+    pub fn single<T>(&self, mut found: Vec<(T, String)>) -> Result<T, Errcode> {
+        match found.len() {
+            0 => Err(Errcode::DocumentNotFound(self.to_string())),
+            1 => Ok(found.pop().unwrap().0),
+            _ => Err(Errcode::DocumentAmbiguous(
+                self.to_string(),
+                found.into_iter().map(|(_, desc)| desc).collect(),
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DocumentKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DocumentKey::Number(number) => write!(f, "{number}"),
+            DocumentKey::Id(id) => write!(f, "with id {id}"),
+        }
+    }
+}
+
+/// Description of a document among others with the same number
+fn candidate(number: &str, id: usize, profile: &Option<String>) -> String {
+    match profile {
+        Some(profile) => format!("{number} (id {id}, profile {profile})"),
+        None => format!("{number} (id {id})"),
+    }
 }
 
 impl TypstData {
-    pub fn new(fname: String, code: String) -> TypstData {
-        TypstData { fname, code }
+    /// Document written with the settings of `cfg`, the configuration of its issuer profile
+    pub fn new(fname: PathBuf, code: String, cfg: &ConfigStore) -> Result<TypstData, Errcode> {
+        Ok(TypstData {
+            fname,
+            code,
+            style: cfg.style_overrides(),
+            assets: vec![],
+            collision: CollisionPolicy::from_config(cfg)?,
+        })
     }
 }
 
@@ -25,36 +105,120 @@ impl TypstData {
 pub enum DocumentType {
     Invoice,
     Quotation,
+    /// Invoices and payments of a contact over a period, never saved
+    Statement,
     // TODO Other document types
     // - contracts
     // - letter
 }
 
 impl DocumentType {
+    /// Generates the document code, changes made to the datastore are not saved here,
+    /// it's up to the caller to export them once the document is written
     pub fn generate_typst(
         &self,
         cfg: &ConfigStore,
-        lang: &LangDict,
-        datadir: &Path,
+        langs: &Languages,
+        data: &mut Datastore,
+        profile: Option<&str>,
+    ) -> Result<TypstData, Errcode> {
+        match self {
+            DocumentType::Invoice => invoice::generate(cfg, langs, data, profile),
+            DocumentType::Quotation => quotation::generate(cfg, langs, data, profile),
+            DocumentType::Statement => statement::generate(cfg, langs, data, profile),
+        }
+    }
+
+    /// Generates the code of a document saved in the history, without changing the datastore
+    pub fn render_typst(
+        &self,
+        cfg: &ConfigStore,
+        langs: &Languages,
+        data: &Datastore,
+        key: &DocumentKey,
+        profile: Option<&str>,
+        use_current_cfg: bool,
     ) -> Result<TypstData, Errcode> {
-        let mut data = Datastore::import(datadir);
-
-        let res = match self {
-            DocumentType::Invoice => invoice::generate(cfg, lang, &mut data),
-            DocumentType::Quotation => quotation::generate(cfg, lang, &mut data),
-        }?;
-        data.export(datadir)?;
-        Ok(res)
+        match self {
+            DocumentType::Invoice => {
+                invoice::render(cfg, langs, data, key, profile, use_current_cfg)
+            }
+            DocumentType::Quotation => {
+                quotation::render(cfg, langs, data, key, profile, use_current_cfg)
+            }
+            DocumentType::Statement => Err(Errcode::DocumentNotFound(format!("statement {key}"))),
+        }
     }
 
     pub fn fname(&self, root: &Path) -> PathBuf {
         root.join(self.to_string()).with_extension("json")
     }
+}
 
-    pub fn export_data<T: Serialize>(&self, root: &Path, data: &T) -> Result<(), Errcode> {
-        let fname = self.fname(root);
-        std::fs::write(fname, serde_json::to_string(data)?)?;
-        Ok(())
+/// Asks for the recipient, and selects the issuer profile: the one asked for, or else
+/// the default profile of the recipient. New contacts keep the profile asked for.
+fn select_recipient(
+    cfg: &ConfigStore,
+    data: &mut Datastore,
+    profile: Option<&str>,
+) -> Result<(Contact, Option<String>, ConfigStore), Errcode> {
+    let slug = Contact::ask_slug();
+    let is_new = !data.contacts.contains(&slug);
+    let mut recipient = data.get_or_add_contact(&slug);
+    if is_new {
+        recipient.profile = profile.map(|p| p.to_string());
+        data.contacts.insert(slug, recipient.clone());
+    }
+    let profile = profile.map(|p| p.to_string()).or(recipient.profile.clone());
+    let cfg = profile_config(cfg, &profile)?;
+    Ok((recipient, profile, cfg))
+}
+
+fn profile_config(cfg: &ConfigStore, profile: &Option<String>) -> Result<ConfigStore, Errcode> {
+    match profile {
+        Some(profile) => cfg.for_profile(profile),
+        None => Ok(cfg.clone()),
+    }
+}
+
+/// Rate of the day to the accounting currency, if the document is written in another one
+fn exchange_rate(
+    cfg: &ConfigStore,
+    data: &Datastore,
+    currency: &str,
+) -> Result<Option<ExchangeRate>, Errcode> {
+    let accounting = cfg.get_str("currency", "accounting");
+    if currency == accounting {
+        return Ok(None);
+    }
+    let today = Utc::now().date_naive();
+    data.rates.rate(currency, accounting, today).map(Some)
+}
+
+/// Currency of the documents sent to a contact, unless another one is chosen
+fn default_currency(cfg: &ConfigStore, recipient: &Contact) -> String {
+    recipient
+        .currency
+        .clone()
+        .unwrap_or_else(|| cfg.get_str("currency", "default").to_string())
+}
+
+/// Issuer, recipient and layout settings used to render again a saved document
+fn render_snapshot(
+    cfg: &ConfigStore,
+    data: &Datastore,
+    doctype: DocumentType,
+    saved: Option<&DocumentSnapshot>,
+    recipient: &String,
+    use_current: bool,
+) -> DocumentSnapshot {
+    match saved {
+        Some(saved) if !use_current => saved.clone(),
+        None if !use_current => {
+            println!("[*] No configuration saved with this document, using the current one");
+            DocumentSnapshot::take(cfg, doctype, data.contacts.get(recipient))
+        }
+        _ => DocumentSnapshot::take(cfg, doctype, data.contacts.get(recipient)),
     }
 }
 
@@ -65,6 +229,7 @@ impl TryFrom<&String> for DocumentType {
         match value.to_lowercase().as_str() {
             "invoice" => Ok(DocumentType::Invoice),
             "quotation" => Ok(DocumentType::Quotation),
+            "statement" => Ok(DocumentType::Statement),
             _ => Err(Errcode::DocTypeUnsupported(value.clone())),
         }
     }
@@ -75,6 +240,7 @@ impl std::fmt::Display for DocumentType {
         match self {
             DocumentType::Invoice => write!(f, "invoice"),
             DocumentType::Quotation => write!(f, "quotation"),
+            DocumentType::Statement => write!(f, "statement"),
         }
     }
 }
@@ -1,26 +1,39 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::PathBuf;
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::audit::AuditEvent;
 use crate::codegen::{
     generate_header, generate_iban, generate_summary_table, generate_transaction_table,
     write_page_settings,
 };
 use crate::config::ConfigStore;
-use crate::contact::Contact;
+use crate::currency::AmountFormat;
 use crate::data::{Datastore, Date, Transaction};
 use crate::errors::Errcode;
-use crate::interface::ask::ask_for_transactions;
-use crate::lang::LangDict;
+use crate::filename::{file_name, FileNameFields};
+use crate::interface::ask::{ask_currency, ask_for_transactions};
+use crate::lang::{LangDict, Languages};
+use crate::numbering::{Counters, NumberingScheme};
+use crate::rates::{Conversion, ExchangeRate};
 
-use super::TypstData;
+use super::snapshot::DocumentSnapshot;
+use super::{
+    candidate, default_currency, exchange_rate, profile_config, render_snapshot, select_recipient,
+    DocumentKey, DocumentType, TypstData,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct QuotationSavedData {
     pub id_counter: usize,
     pub history: HashMap<String, Vec<(QuotationInput, Option<usize>)>>,
+    #[serde(default)]
+    pub counters: Counters,
+    /// Numbering sequences of the other issuer profiles
+    #[serde(default)]
+    pub profile_counters: HashMap<String, Counters>,
 }
 
 impl QuotationSavedData {
@@ -28,23 +41,47 @@ impl QuotationSavedData {
         QuotationSavedData {
             id_counter: 1,
             history: HashMap::new(),
+            counters: HashMap::new(),
+            profile_counters: HashMap::new(),
         }
     }
 
-    pub fn import(fname: &Path) -> QuotationSavedData {
-        if !fname.is_file() {
-            return QuotationSavedData::init();
-        }
+    /// Finds a quotation from its number, or its internal id, with the invoice made from it,
+    /// among the quotations of the issuer profile if set
+    pub fn find(
+        &self,
+        cfg: &ConfigStore,
+        key: &DocumentKey,
+        profile: Option<&str>,
+    ) -> Result<&(QuotationInput, Option<usize>), Errcode> {
+        let found = self
+            .history
+            .values()
+            .flatten()
+            .filter(|(inp, _)| profile.is_none() || (inp.profile.as_deref() == profile))
+            .map(|quote| (quote, quote.0.display_number(cfg)))
+            .filter(|(quote, number)| key.matches(quote.0.id, number))
+            .map(|(quote, number)| (quote, candidate(&number, quote.0.id, &quote.0.profile)))
+            .collect();
+        key.single(found)
+    }
 
-        let json_str =
-            std::fs::read_to_string(fname).expect("Unable to read JSON data from {fname}");
-        match serde_json::from_str::<Self>(json_str.as_str()) {
-            Ok(d) => d,
-            Err(_) => {
-                println!("Failed to import the quotation data");
-                QuotationSavedData::init()
-            }
-        }
+    /// Takes the next number of the issuer profile
+    pub fn allocate_number(
+        &mut self,
+        cfg: &ConfigStore,
+        profile: &Option<String>,
+        id: usize,
+    ) -> Result<String, Errcode> {
+        let scheme = NumberingScheme::from_config(cfg, "quotation")?;
+        Ok(match profile {
+            Some(p) => scheme.allocate(
+                self.profile_counters.entry(p.clone()).or_default(),
+                None,
+                &Utc::now(),
+            ),
+            None => scheme.allocate(&mut self.counters, Some(id), &Utc::now()),
+        })
     }
 
     pub fn mark_quotation_finished(
@@ -83,38 +120,90 @@ pub struct QuotationInput {
     pub recipient: String,
     pub created: Date,
     pub tx: Vec<Transaction>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub snapshot: Option<DocumentSnapshot>,
+    #[serde(default)]
+    pub number: Option<String>,
+    /// Issuer profile, none for the main one
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// ISO 4217 code, none for the documents saved before currencies were supported
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Rate to the accounting currency, for the documents written in another one
+    #[serde(default)]
+    pub exchange_rate: Option<ExchangeRate>,
+    /// Language the document is written in, none for the default one
+    #[serde(default)]
+    pub lang: Option<String>,
 }
 
 impl QuotationInput {
-    pub fn single_line_display(&self) -> String {
-        let total_price: f64 = self.tx.iter().map(|(_, u, p)| u * p).sum();
+    pub fn total_no_tax(&self) -> f64 {
+        self.tx.iter().map(|(_, units, ppu)| units * ppu).sum()
+    }
+
+    /// Quotations saved before the numbering schemes existed only have their id
+    pub fn display_number(&self, cfg: &ConfigStore) -> String {
+        self.number
+            .clone()
+            .unwrap_or_else(|| format!("{}{:0>5}", cfg.get_str("quotation", "id_prefix"), self.id))
+    }
+
+    pub fn currency_code(&self, cfg: &ConfigStore) -> String {
+        self.currency
+            .clone()
+            .unwrap_or_else(|| cfg.get_str("currency", "default").to_string())
+    }
+
+    pub fn single_line_display(&self, cfg: &ConfigStore, lang: &LangDict) -> String {
+        let total_price = AmountFormat::new(lang, &self.currency_code(cfg))
+            .map(|amounts| amounts.format(self.total_no_tax()))
+            .unwrap_or_else(|_| format!("{:.2}", self.total_no_tax()));
         let descr = self
             .tx
             .iter()
             .map(|(d, _, _)| d.clone())
             .collect::<Vec<String>>()
             .join(", ");
-        let line = format!(
-            "{} {} {total_price:.2}€ : {descr}",
-            self.recipient, self.created,
-        );
-        if line.len() > 80 {
-            line[..80].to_string() + "..."
+        let created = self
+            .created
+            .localized(lang)
+            .unwrap_or_else(|_| self.created.to_string());
+        let line = format!("{} {created} {total_price} : {descr}", self.recipient);
+        // Dates and amounts can have characters of several bytes
+        if line.chars().count() > 80 {
+            line.chars().take(80).collect::<String>() + "..."
         } else {
             line
         }
     }
 
-    pub fn ask(id: usize, recipient: String, _: &ConfigStore, lang: &LangDict) -> QuotationInput {
+    pub fn ask(
+        id: usize,
+        number: String,
+        recipient: String,
+        profile: Option<String>,
+        currency: String,
+        lang: &LangDict,
+    ) -> QuotationInput {
         let current_date = Utc::now();
-        let created = lang.get_date_fmt(&current_date);
-
+        let created = Date::Day(current_date.date_naive());
         let tx = ask_for_transactions(lang);
         QuotationInput {
             id,
             recipient,
             created,
             tx,
+            created_at: Some(current_date),
+            snapshot: None,
+            number: Some(number),
+            profile,
+            currency: Some(currency),
+            exchange_rate: None,
+            lang: None,
         }
     }
 }
@@ -122,33 +211,49 @@ impl QuotationInput {
 pub struct QuotationBuilder<'a> {
     cfg: &'a ConfigStore,
     lang: &'a LangDict,
-    data: &'a mut Datastore,
+    snap: &'a DocumentSnapshot,
     inp: &'a QuotationInput,
+    invoiced: bool,
 }
 
 impl<'a> QuotationBuilder<'a> {
-    pub fn generate_quotation(&mut self) -> Result<(String, String), Errcode> {
-        // Getting necessary data before writing the code
-        self.data.quotations.add_quote(self.inp);
-        let current_date = Utc::now();
+    pub fn generate_quotation(&self) -> Result<(PathBuf, String), Errcode> {
+        // Quotations saved before the creation date was stored are named after the current date
+        let fields = FileNameFields {
+            doctype: "quotation",
+            number: &self.inp.display_number(self.cfg),
+            recipient: &self.inp.recipient,
+            recipient_name: &self.snap.recipient.name,
+            status: if self.invoiced { "invoiced" } else { "pending" },
+            created_at: self.inp.created_at.unwrap_or_else(Utc::now),
+        };
+        let fname = file_name(&self.snap.layout.filename, &fields)?;
 
-        let fname = format!(
-            "quotation_{}_{}_{}.pdf",
-            self.inp.recipient,
-            self.data.quotations.history.len(),
-            current_date.format("%d%m%y"),
-        );
-
-        let footer = self.cfg.get_str("quotation", "footer");
         let mut source = "".to_string();
-        write_page_settings(&mut source, footer);
-        generate_header(self.cfg, &mut source);
+        write_page_settings(&mut source, &self.snap.layout.footer);
+        generate_header(&self.snap.issuer, &mut source);
         source += "#v(sep_par())\n";
-        self.generate_metadata(&mut source);
+        self.generate_metadata(&mut source)?;
         source += "#v(sep_par())\n";
-        let total_price = generate_transaction_table(&mut source, &self.inp.tx, self.lang);
+        let amounts = AmountFormat::new(self.lang, &self.inp.currency_code(self.cfg))?;
+        let conversion = match self.inp.exchange_rate.as_ref() {
+            Some(rate) => Some(Conversion {
+                rate,
+                amounts: AmountFormat::new(self.lang, &rate.currency)?,
+            }),
+            None => None,
+        };
+        let total_price =
+            generate_transaction_table(&mut source, &self.inp.tx, self.lang, &amounts);
         source += "#v(sep_par())\n";
-        generate_summary_table(&mut source, total_price, self.lang, self.cfg);
+        generate_summary_table(
+            &mut source,
+            total_price,
+            self.lang,
+            &self.snap.taxes,
+            &amounts,
+            conversion.as_ref(),
+        );
         source += "#v(sep_par())\n";
         source += format!(
             "=== {}\n",
@@ -156,18 +261,18 @@ impl<'a> QuotationBuilder<'a> {
                 .get_doctype_word("quotation", "payment_conditions")
         )
         .as_str();
-        source += self.cfg.get_str("quotation", "payment_conditions");
+        source += &self.snap.layout.payment_conditions;
         source += "\n";
 
-        if self.cfg.get_bool("quotation", "add_iban") {
-            generate_iban(&mut source, self.lang, self.cfg);
+        if self.snap.layout.add_iban {
+            generate_iban(&mut source, self.lang, &self.snap.bank, None);
         }
 
         source += "\n";
         Ok((fname, source))
     }
 
-    fn generate_metadata(&self, source: &mut String) {
+    fn generate_metadata(&self, source: &mut String) -> Result<(), Errcode> {
         *source += format!(
             "#grid(
             columns: (1fr, 1fr),
@@ -177,42 +282,92 @@ impl<'a> QuotationBuilder<'a> {
                 {} \\ {} \\
             ],
             align(right)[
-                {} \\#*{}{:0>5}* \\
+                {} \\#*{}* \\
                 {} *{}* \\
             ],
         )",
             self.lang.get_doctype_word("quotation", "recipient_intro"),
-            self.data.contacts.get(&self.inp.recipient).name,
-            self.data.contacts.get(&self.inp.recipient).address,
+            self.snap.recipient.name,
+            self.snap.recipient.address,
             self.lang.get_doctype_word("quotation", "quotation_nb"),
-            self.cfg.get_str("quotation", "id_prefix"),
-            self.data.quotations.history.len(),
+            self.inp.display_number(self.cfg),
             self.lang.get_doctype_word("general", "creation_date"),
-            self.inp.created,
+            self.inp.created.localized(self.lang)?,
         )
         .as_str();
+        Ok(())
     }
 }
 
 pub fn generate(
     cfg: &ConfigStore,
-    lang: &LangDict,
+    langs: &Languages,
     data: &mut Datastore,
+    profile: Option<&str>,
 ) -> Result<TypstData, Errcode> {
-    let recipient_slug = Contact::ask_slug();
-    data.contacts.get_or_add(&recipient_slug);
+    let (recipient, profile, cfg) = select_recipient(cfg, data, profile)?;
+    let cfg = &cfg;
+    let (lang_code, lang) = langs.select(cfg, &recipient.lang)?;
+    let lang = &lang;
+    let recipient_slug = recipient.slug.clone();
     let id = data.quotations.id_counter;
     data.quotations.id_counter += 1;
+    let number = data.quotations.allocate_number(cfg, &profile, id)?;
     data.contacts.get_mut(&recipient_slug).quotations.push(id);
-    let inp = QuotationInput::ask(id, recipient_slug, cfg, lang);
-    let mut builder = QuotationBuilder {
+    // The rate is needed before the details of the quotation are typed in
+    let currency = ask_currency(&default_currency(cfg, &recipient));
+    let rate = exchange_rate(cfg, data, &currency)?;
+    let mut inp = QuotationInput::ask(id, number, recipient_slug, profile, currency, lang);
+    inp.exchange_rate = rate;
+    inp.lang = Some(lang_code);
+    let snapshot = DocumentSnapshot::take(cfg, DocumentType::Quotation, &recipient);
+    let builder = QuotationBuilder {
         cfg,
         lang,
-        data,
+        snap: &snapshot,
         inp: &inp,
+        invoiced: false,
+    };
+    let (fname, result) = builder.generate_quotation()?;
+    inp.snapshot = Some(snapshot);
+    data.audit.push(AuditEvent::QuotationCreated {
+        id: inp.id,
+        number: inp.number.clone(),
+        recipient: inp.recipient.clone(),
+        total_no_tax: inp.total_no_tax(),
+        currency: inp.currency.clone(),
+    });
+    data.quotations.add_quote(&inp);
+    TypstData::new(fname, result, cfg)
+}
+
+pub fn render(
+    cfg: &ConfigStore,
+    langs: &Languages,
+    data: &Datastore,
+    key: &DocumentKey,
+    profile: Option<&str>,
+    use_current_cfg: bool,
+) -> Result<TypstData, Errcode> {
+    let (inp, invoice) = data.quotations.find(cfg, key, profile)?;
+    let cfg = &profile_config(cfg, &inp.profile)?;
+    let snap = render_snapshot(
+        cfg,
+        data,
+        DocumentType::Quotation,
+        inp.snapshot.as_ref(),
+        &inp.recipient,
+        use_current_cfg,
+    );
+    let (_, lang) = langs.select(cfg, &inp.lang)?;
+    let lang = &lang;
+    let builder = QuotationBuilder {
+        cfg,
+        lang,
+        snap: &snap,
+        inp,
+        invoiced: invoice.is_some(),
     };
     let (fname, result) = builder.generate_quotation()?;
-    // For debug
-    std::fs::write("/tmp/.typst_result.typ", &result)?;
-    Ok(TypstData::new(fname, result))
+    TypstData::new(fname, result, cfg)
 }
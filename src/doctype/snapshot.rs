@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigStore;
+use crate::contact::Contact;
+
+use super::DocumentType;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Issuer {
+    pub name: String,
+    pub person_name: String,
+    pub address: String,
+    pub email: String,
+    pub legal_status: String,
+    pub siret_number: String,
+    pub logo_path: String,
+    pub logo_writing: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BankDetails {
+    pub name: String,
+    pub iban: String,
+    pub bic: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TaxSettings {
+    pub tax_applicable: bool,
+    pub tax_rate: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Recipient {
+    pub slug: String,
+    pub name: String,
+    pub address: String,
+}
+
+/// Account and structured address of the company printed on the Swiss QR-bills
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QrBillSettings {
+    /// The IBAN of the bank details if empty
+    pub iban: String,
+    pub street: String,
+    pub building_number: String,
+    pub postcode: String,
+    pub town: String,
+    pub country: String,
+}
+
+/// Settings of the document type changing what is printed on the document
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LayoutSettings {
+    pub filename: String,
+    pub footer: String,
+    pub add_iban: bool,
+    /// Invoices only
+    pub add_payment_qr: bool,
+    /// Quotations only
+    pub payment_conditions: String,
+    /// Invoices only, none if they have no QR-bill
+    pub qr_bill: Option<QrBillSettings>,
+}
+
+/// Details about both parties as they were when the document was created,
+/// so changing the config or a contact never alters an issued document
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DocumentSnapshot {
+    pub issuer: Issuer,
+    pub bank: BankDetails,
+    pub taxes: TaxSettings,
+    pub recipient: Recipient,
+    pub layout: LayoutSettings,
+}
+
+impl Issuer {
+    pub fn from_config(cfg: &ConfigStore) -> Issuer {
+        Issuer {
+            name: cfg.get_company("name"),
+            person_name: cfg.get_company("person_name"),
+            address: cfg.get_company("address"),
+            email: cfg.get_company("email"),
+            legal_status: cfg.get_company("legal_status"),
+            siret_number: cfg.get_company("siret_number"),
+            logo_path: cfg.get_company("logo_path"),
+            logo_writing: cfg.get_company("logo_writing"),
+        }
+    }
+}
+
+impl QrBillSettings {
+    pub fn from_config(cfg: &ConfigStore) -> QrBillSettings {
+        let setting = |name| cfg.get_str("qr_bill", name).to_string();
+        QrBillSettings {
+            iban: setting("iban"),
+            street: setting("street"),
+            building_number: setting("building_number"),
+            postcode: setting("postcode"),
+            town: setting("town"),
+            country: setting("country"),
+        }
+    }
+}
+
+impl LayoutSettings {
+    pub fn from_config(cfg: &ConfigStore, doctype: DocumentType) -> LayoutSettings {
+        let table = doctype.to_string();
+        let is_invoice = doctype == DocumentType::Invoice;
+        LayoutSettings {
+            filename: cfg.get_str(&table, "filename").to_string(),
+            footer: cfg.get_str(&table, "footer").to_string(),
+            add_iban: cfg.get_bool(&table, "add_iban"),
+            add_payment_qr: is_invoice && cfg.get_bool("invoice", "add_payment_qr"),
+            payment_conditions: match doctype {
+                DocumentType::Quotation => cfg.get_str("quotation", "payment_conditions"),
+                _ => "",
+            }
+            .to_string(),
+            qr_bill: (is_invoice && cfg.get_bool("qr_bill", "add_qr_bill"))
+                .then(|| QrBillSettings::from_config(cfg)),
+        }
+    }
+}
+
+impl DocumentSnapshot {
+    pub fn take(cfg: &ConfigStore, doctype: DocumentType, recipient: &Contact) -> DocumentSnapshot {
+        DocumentSnapshot {
+            issuer: Issuer::from_config(cfg),
+            bank: BankDetails {
+                name: cfg.get_str("bank", "name").to_string(),
+                iban: cfg.get_str("bank", "iban").to_string(),
+                bic: cfg.get_str("bank", "bic").to_string(),
+            },
+            taxes: TaxSettings {
+                tax_applicable: cfg.get_bool("taxes", "tax_applicable"),
+                tax_rate: cfg.get_float("taxes", "tax_rate"),
+            },
+            recipient: Recipient {
+                slug: recipient.slug.clone(),
+                name: recipient.name.clone(),
+                address: recipient.address.clone(),
+            },
+            layout: LayoutSettings::from_config(cfg, doctype),
+        }
+    }
+}
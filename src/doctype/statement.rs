@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, NaiveTime, Utc};
+
+use crate::codegen::{generate_header, generate_iban, sanitize, write_page_settings};
+use crate::config::ConfigStore;
+use crate::contact::Contact;
+use crate::currency::AmountFormat;
+use crate::data::{Datastore, Date};
+use crate::errors::Errcode;
+use crate::filename::{file_name, FileNameFields};
+use crate::interface::ask::ask_date;
+use crate::lang::{LangDict, Languages};
+
+use super::snapshot::DocumentSnapshot;
+use super::{profile_config, DocumentType, TypstData};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum MovementKind {
+    Invoice,
+    /// Invoice with a negative total
+    CreditNote,
+    Payment,
+}
+
+/// Change of the balance of a contact
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementLine {
+    pub date: NaiveDate,
+    pub kind: MovementKind,
+    /// Number of the invoice, and reference of the payment if any
+    pub reference: String,
+    pub debit: f64,
+    pub credit: f64,
+    /// Balance after this line
+    pub balance: f64,
+}
+
+/// Movements of the account of a contact in a currency, over a period
+#[derive(Debug)]
+pub struct Statement {
+    pub currency: String,
+    pub opening: f64,
+    pub lines: Vec<StatementLine>,
+    pub closing: f64,
+}
+
+/// Statements of the invoices of a contact and their payments between `from` and `to`, one
+/// per currency. The invoices with a creation date that could not be understood are left out.
+pub fn statements(
+    cfg: &ConfigStore,
+    data: &Datastore,
+    contact: &Contact,
+    profile: &Option<String>,
+    (from, to): (NaiveDate, NaiveDate),
+) -> Vec<Statement> {
+    // Positive amounts are owed by the contact
+    let mut movements: BTreeMap<String, Vec<(NaiveDate, MovementKind, String, f64)>> =
+        BTreeMap::new();
+    for inp in data.invoices.history.iter() {
+        if !contact.invoices.contains(&inp.id) || (inp.profile != *profile) {
+            continue;
+        }
+        let Date::Day(created) = inp.created else {
+            continue;
+        };
+        let number = inp.display_number(cfg);
+        let total = inp.total_with_tax();
+        let kind = if total < 0.0 {
+            MovementKind::CreditNote
+        } else {
+            MovementKind::Invoice
+        };
+        let account = movements.entry(inp.currency_code(cfg)).or_default();
+        account.push((created, kind, number.clone(), total));
+        for payment in inp.payments.iter() {
+            let reference = match payment.reference {
+                Some(ref r) => format!("{number} – {r}"),
+                None => number.clone(),
+            };
+            account.push((
+                payment.date,
+                MovementKind::Payment,
+                reference,
+                -payment.amount,
+            ));
+        }
+    }
+
+    let mut res = vec![];
+    for (currency, mut account) in movements.into_iter() {
+        account.sort_by_key(|(date, kind, ..)| (*date, *kind));
+        let opening: f64 = account
+            .iter()
+            .filter(|(date, ..)| *date < from)
+            .map(|(.., amount)| amount)
+            .sum();
+        let mut balance = opening;
+        let mut lines = vec![];
+        for (date, kind, reference, amount) in account.into_iter() {
+            if (date < from) || (date > to) {
+                continue;
+            }
+            balance += amount;
+            lines.push(StatementLine {
+                date,
+                kind,
+                reference,
+                debit: amount.max(0.0),
+                credit: (-amount).max(0.0),
+                balance,
+            });
+        }
+        if lines.is_empty() && (opening.abs() < 0.005) {
+            continue;
+        }
+        res.push(Statement {
+            currency,
+            opening,
+            lines,
+            closing: balance,
+        });
+    }
+    res
+}
+
+pub struct StatementBuilder<'a> {
+    lang: &'a LangDict,
+    snap: &'a DocumentSnapshot,
+    period: (NaiveDate, NaiveDate),
+    statements: &'a [Statement],
+}
+
+impl<'a> StatementBuilder<'a> {
+    pub fn generate_statement(&self) -> Result<(PathBuf, String), Errcode> {
+        let (from, to) = self.period;
+        let number = format!("{}-{}", from.format("%Y%m%d"), to.format("%Y%m%d"));
+        let fields = FileNameFields {
+            doctype: "statement",
+            number: &number,
+            recipient: &self.snap.recipient.slug,
+            recipient_name: &self.snap.recipient.name,
+            status: "issued",
+            created_at: to.and_time(NaiveTime::MIN).and_utc(),
+        };
+        let fname = file_name(&self.snap.layout.filename, &fields)?;
+
+        let mut source = "".to_string();
+        write_page_settings(&mut source, &self.snap.layout.footer);
+        generate_header(&self.snap.issuer, &mut source);
+        source += "#v(sep_par())\n";
+        self.generate_metadata(&mut source)?;
+        for statement in self.statements.iter() {
+            source += "#v(sep_par())\n";
+            self.generate_movements(&mut source, statement)?;
+        }
+        source += "#v(sep_par())\n";
+
+        if self.snap.layout.add_iban {
+            generate_iban(&mut source, self.lang, &self.snap.bank, None);
+        }
+
+        source += "\n";
+        Ok((fname, source))
+    }
+
+    fn generate_metadata(&self, source: &mut String) -> Result<(), Errcode> {
+        *source += format!(
+            "#grid(
+            columns: (1fr, 1fr),
+            column-gutter: 10%,
+            align(left)[
+                #text(17pt)[{}] \\
+                {} \\ {} \\
+            ],
+            align(right)[
+                #text(17pt)[*{}*] \\
+                {} *{}* – *{}* \\
+                {} *{}* \\
+            ],
+        )",
+            self.lang.get_doctype_word("statement", "recipient_intro"),
+            sanitize(&self.snap.recipient.name),
+            sanitize(&self.snap.recipient.address),
+            self.lang.get_doctype_word("statement", "title"),
+            self.lang.get_doctype_word("statement", "period"),
+            self.lang.format_date(&self.period.0)?,
+            self.lang.format_date(&self.period.1)?,
+            self.lang.get_doctype_word("general", "creation_date"),
+            self.lang.format_date(&Utc::now().date_naive())?,
+        )
+        .as_str();
+        Ok(())
+    }
+
+    fn generate_movements(
+        &self,
+        source: &mut String,
+        statement: &Statement,
+    ) -> Result<(), Errcode> {
+        let word = |w| self.lang.get_doctype_word("statement", w);
+        let amounts = AmountFormat::new(self.lang, &statement.currency)?;
+        let fmt = |amount: f64| {
+            if amount == 0.0 {
+                "".to_string()
+            } else {
+                sanitize(&amounts.format(amount))
+            }
+        };
+        *source += format!(
+            "#table(
+        stroke: table_color(),
+        columns: (auto, tx_descr_width(), 1fr, 1fr, 1fr),
+        [*{}*], [*{}*], [*{}*], [*{}*], [*{}*],
+        [], [*{}*], [], [], [*{}*],
+",
+            word("date"),
+            word("document"),
+            word("debit"),
+            word("credit"),
+            word("balance"),
+            word("opening_balance"),
+            sanitize(&amounts.format(statement.opening)),
+        )
+        .as_str();
+
+        for line in statement.lines.iter() {
+            let kind = match line.kind {
+                MovementKind::Invoice => word("invoice"),
+                MovementKind::CreditNote => word("credit_note"),
+                MovementKind::Payment => word("payment"),
+            };
+            *source += format!(
+                "    [{}], [{kind} {}], [{}], [{}], [{}],\n",
+                self.lang.format_date(&line.date)?,
+                sanitize(&line.reference),
+                fmt(line.debit),
+                fmt(line.credit),
+                sanitize(&amounts.format(line.balance)),
+            )
+            .as_str();
+        }
+
+        *source += format!(
+            "    [], [*{}*], [], [], [*{}*],\n)\n",
+            word("closing_balance"),
+            sanitize(&amounts.format(statement.closing)),
+        )
+        .as_str();
+        Ok(())
+    }
+}
+
+pub fn generate(
+    cfg: &ConfigStore,
+    langs: &Languages,
+    data: &mut Datastore,
+    profile: Option<&str>,
+) -> Result<TypstData, Errcode> {
+    // Statements are made from the saved documents, so the contact must exist
+    let slug = Contact::ask_slug();
+    if !data.contacts.contains(&slug) {
+        return Err(Errcode::ContactNotFound(slug));
+    }
+    let recipient = data.contacts.get(&slug).clone();
+    let profile = profile.map(|p| p.to_string()).or(recipient.profile.clone());
+    let cfg = &profile_config(cfg, &profile)?;
+    let (_, lang) = langs.select(cfg, &recipient.lang)?;
+    let lang = &lang;
+
+    let from = ask_date("Enter the first day of the statement: ", lang);
+    let to = ask_date("Enter the last day of the statement: ", lang);
+    let statements = statements(cfg, data, &recipient, &profile, (from, to));
+    let snapshot = DocumentSnapshot::take(cfg, DocumentType::Statement, &recipient);
+    let builder = StatementBuilder {
+        lang,
+        snap: &snapshot,
+        period: (from, to),
+        statements: &statements,
+    };
+    let (fname, result) = builder.generate_statement()?;
+    TypstData::new(fname, result, cfg)
+}
+
+#[test]
+fn statement_balance() {
+    use crate::contact::ContactBook;
+    use crate::doctype::quotation::QuotationSavedData;
+    use crate::rates::RateTable;
+
+    let data = Datastore {
+        contacts: ContactBook::default(),
+        invoices: serde_json::from_str(
+            r#"{"id_counter": 5, "history": [
+            {"id": 1, "recipient": "acme", "quote_nb": null, "date_sell": "2026-01-10",
+             "tx": [["Audit", 1.0, 1000.0]], "tax_rate": 0.2, "created": "2026-01-12",
+             "payments": [{"date": "2026-02-20", "amount": 1000.0, "reference": "VIR 42"}]},
+            {"id": 2, "recipient": "acme", "quote_nb": null, "date_sell": "2026-02-02",
+             "tx": [["Support", 1.0, 300.0]], "tax_rate": null, "created": "2026-02-03"},
+            {"id": 3, "recipient": "acme", "quote_nb": null, "date_sell": "2026-02-02",
+             "tx": [["Refund", 1.0, -100.0]], "tax_rate": null, "created": "2026-02-20"},
+            {"id": 4, "recipient": "initech", "quote_nb": null, "date_sell": "2026-02-02",
+             "tx": [["Audit", 1.0, 50.0]], "tax_rate": null, "created": "2026-02-10"}]}"#,
+        )
+        .unwrap(),
+        quotations: QuotationSavedData::init(),
+        audit: vec![],
+        rates: RateTable::default(),
+    };
+    let cfg: ConfigStore = toml::from_str(include_str!("../../default/config.toml")).unwrap();
+    let contact: Contact = serde_json::from_str(
+        r#"{"slug": "acme", "name": "ACME", "address": "Paris",
+            "invoices": [1, 2, 3], "quotations": []}"#,
+    )
+    .unwrap();
+    let day = |m, d| NaiveDate::from_ymd_opt(2026, m, d).unwrap();
+    let res = statements(&cfg, &data, &contact, &None, (day(2, 1), day(2, 28)));
+    assert_eq!(res.len(), 1);
+    let statement = &res[0];
+    assert_eq!(statement.opening, 1200.0);
+    let lines: Vec<(MovementKind, f64, f64, f64)> = statement
+        .lines
+        .iter()
+        .map(|l| (l.kind, l.debit, l.credit, l.balance))
+        .collect();
+    // On the same day, the credit note comes before the payment
+    assert_eq!(
+        lines,
+        [
+            (MovementKind::Invoice, 300.0, 0.0, 1500.0),
+            (MovementKind::CreditNote, 0.0, 100.0, 1400.0),
+            (MovementKind::Payment, 0.0, 1000.0, 400.0),
+        ]
+    );
+    assert_eq!(statement.lines[2].reference, "F00001 – VIR 42");
+    assert_eq!(statement.closing, 400.0);
+}
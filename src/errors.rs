@@ -6,6 +6,22 @@ pub enum Errcode {
     InvalidConfig(&'static str, String),
     ContactNotFound(String),
     HistoryElementNotFound(usize),
+    DocumentNotFound(String),
+    DocumentAmbiguous(String, Vec<String>),
+    ProfileNotFound(String),
+    LangNotFound(String),
+    InvalidCurrency(String),
+    InvalidAmount(f64),
+    ExchangeRateMissing(String, String, String),
+    OutputFileExists(String),
+    TypstCompilation(String),
+    StorageNotEmpty(String),
+    StorageMigrationMismatch(String, String),
+    DataCorrupted(String, String),
+    DataVersionUnsupported(String, usize),
+    DataLocked(String),
+    AuditMismatch(usize),
+    AuditLogMissing,
 
     IoError(#[from] std::io::Error),
     TomlDecode(#[from] toml::de::Error),
@@ -14,6 +30,38 @@ pub enum Errcode {
     JsonDecode(#[from] serde_json::Error),
     ReqwestError(#[from] reqwest::Error),
     ZipArchive(#[from] zip::result::ZipError),
+    SqliteError(#[from] rusqlite::Error),
+    QrCode(#[from] qrcode::types::QrError),
+}
+
+impl Errcode {
+    /// Exit code of the program when stopped by this error, 2 is used by clap for
+    /// invalid arguments
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Errcode::DocTypeUnsupported(_)
+            | Errcode::ContactNotFound(_)
+            | Errcode::HistoryElementNotFound(_)
+            | Errcode::DocumentNotFound(_)
+            | Errcode::DocumentAmbiguous(..)
+            | Errcode::ProfileNotFound(_)
+            | Errcode::LangNotFound(_) => 3,
+            Errcode::InvalidAmount(_) => 2,
+            Errcode::InvalidConfig(..)
+            | Errcode::InvalidCurrency(_)
+            | Errcode::ExchangeRateMissing(..)
+            | Errcode::TomlDecode(_) => 4,
+            Errcode::StorageNotEmpty(_)
+            | Errcode::StorageMigrationMismatch(..)
+            | Errcode::DataCorrupted(..)
+            | Errcode::DataVersionUnsupported(..)
+            | Errcode::DataLocked(_)
+            | Errcode::AuditMismatch(_)
+            | Errcode::AuditLogMissing => 5,
+            Errcode::OutputFileExists(_) => 6,
+            _ => 1,
+        }
+    }
 }
 
 impl std::fmt::Display for Errcode {
@@ -27,6 +75,42 @@ impl std::fmt::Display for Errcode {
                 }
                 writeln!(f, "Message: {}", e)?;
             }
+            Errcode::DocTypeUnsupported(doctype) => {
+                write!(f, "Unsupported document type {doctype}")?
+            }
+            Errcode::InvalidConfig(section, msg) => write!(f, "Invalid {section} setting: {msg}")?,
+            Errcode::InvalidAmount(amount) => write!(
+                f,
+                "Invalid amount {amount}, a payment must be a finite, non-zero number"
+            )?,
+            Errcode::ContactNotFound(slug) => write!(f, "No contact {slug} found")?,
+            Errcode::DocumentNotFound(number) => write!(f, "No document {number} found")?,
+            Errcode::DocumentAmbiguous(number, candidates) => write!(
+                f,
+                "Several documents {number} found: {}, select one with --profile or --id",
+                candidates.join(", ")
+            )?,
+            Errcode::DataLocked(holder) => {
+                write!(f, "The data directory is being used by {holder}")?;
+            }
+            Errcode::ProfileNotFound(name) => {
+                write!(f, "No profile {name} defined in the [profiles] config")?
+            }
+            Errcode::LangNotFound(code) => write!(
+                f,
+                "No language pack {code}, add a lang/{code}.toml file to the root directory"
+            )?,
+            Errcode::ExchangeRateMissing(from, to, date) => write!(
+                f,
+                "No exchange rate from {from} to {to} published in the week before {date}, \
+                add the latest ECB rates with `docgen import-rates <file>`"
+            )?,
+            Errcode::AuditMismatch(nb) => write!(f, "{nb} problems found in the audit log")?,
+            Errcode::AuditLogMissing => write!(
+                f,
+                "The audit log is missing, run `docgen init-audit` to start it"
+            )?,
+            Errcode::OutputFileExists(fname) => write!(f, "The file {fname} already exists")?,
             e => write!(f, "{e:?}")?,
         }
         Ok(())
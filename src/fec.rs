@@ -0,0 +1,470 @@
+use chrono::NaiveDate;
+
+use crate::config::ConfigStore;
+use crate::data::{Datastore, Date};
+use crate::doctype::invoice::{InvoiceInput, Payment};
+use crate::errors::Errcode;
+
+/// Columns mandated by the article A47 A-1 of the Livre des procédures fiscales
+const FEC_COLUMNS: [&str; 18] = [
+    "JournalCode",
+    "JournalLib",
+    "EcritureNum",
+    "EcritureDate",
+    "CompteNum",
+    "CompteLib",
+    "CompAuxNum",
+    "CompAuxLib",
+    "PieceRef",
+    "PieceDate",
+    "EcritureLib",
+    "Debit",
+    "Credit",
+    "EcritureLet",
+    "DateLet",
+    "ValidDate",
+    "Montantdevise",
+    "Idevise",
+];
+
+/// Accounts and journals of the entries, from the `[fec]` config
+pub struct FecSettings {
+    journal: (String, String),
+    bank_journal: (String, String),
+    customer: (String, String),
+    customer_aux_prefix: String,
+    revenue: (String, String),
+    vat: (String, String),
+    bank: (String, String),
+    separator: char,
+}
+
+impl FecSettings {
+    pub fn from_config(cfg: &ConfigStore) -> Result<FecSettings, Errcode> {
+        let get = |key| cfg.get_str("fec", key).to_string();
+        let separator = match cfg.get_str("fec", "separator") {
+            "|" => '|',
+            "tab" => '\t',
+            s => {
+                return Err(Errcode::InvalidConfig(
+                    "fec",
+                    format!("Unknown separator {s:?}"),
+                ))
+            }
+        };
+        Ok(FecSettings {
+            journal: (get("journal_code"), get("journal_name")),
+            bank_journal: (get("bank_journal_code"), get("bank_journal_name")),
+            customer: (get("customer_account"), get("customer_account_name")),
+            customer_aux_prefix: get("customer_aux_prefix"),
+            revenue: (get("revenue_account"), get("revenue_account_name")),
+            vat: (get("vat_account"), get("vat_account_name")),
+            bank: (get("bank_account"), get("bank_account_name")),
+            separator,
+        })
+    }
+}
+
+/// First and last day of the fiscal year ending in `year`
+pub fn fiscal_year(cfg: &ConfigStore, year: i32) -> Result<(NaiveDate, NaiveDate), Errcode> {
+    let end = cfg.get_str("fec", "fiscal_year_end");
+    let invalid = || Errcode::InvalidConfig("fec", format!("Invalid fiscal year end {end:?}"));
+    let date = |year| NaiveDate::parse_from_str(&format!("{year}-{end}"), "%Y-%m-%d");
+    // Fiscal years cannot end on February 29th, which does not exist every year
+    let last = date(year).map_err(|_| invalid())?;
+    let start = date(year - 1).map_err(|_| invalid())?.succ_opt().unwrap();
+    Ok((start, last))
+}
+
+/// Name of the file, made of the SIREN and the closing date of the fiscal year
+pub fn fec_fname(cfg: &ConfigStore, end: &NaiveDate) -> Result<String, Errcode> {
+    let siren: String = cfg
+        .get_company("siret_number")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .take(9)
+        .collect();
+    if (siren.len() != 9) || !siren.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Errcode::InvalidConfig(
+            "company",
+            "The SIRET number must start with the 9 digits of the SIREN".to_string(),
+        ));
+    }
+    Ok(format!("{siren}FEC{}.txt", end.format("%Y%m%d")))
+}
+
+struct EntryLine<'a> {
+    account: &'a (String, String),
+    aux: Option<(String, String)>,
+    label: String,
+    /// Debit if positive, credit if negative, in the accounting currency
+    amount: f64,
+    /// Amount in the currency of the invoice, if it is another one
+    foreign: Option<(f64, String)>,
+    /// Letter matching the lines of an invoice and of its payments, and the day it was paid
+    lettering: Option<(String, NaiveDate)>,
+}
+
+/// Journal, number and date of an accounting entry, with its supporting document
+struct EntryHeader<'a> {
+    journal: &'a (String, String),
+    num: usize,
+    date: NaiveDate,
+    piece_ref: String,
+    piece_date: NaiveDate,
+}
+
+/// Entries of the invoices of the fiscal year in the sales journal, then of the payments
+/// received in the bank journal, and the number of invoices left out because their
+/// creation date could not be understood.
+/// The customer lines of the invoices fully paid within the fiscal year are lettered.
+pub fn fec_entries(
+    cfg: &ConfigStore,
+    data: &Datastore,
+    profile: &Option<String>,
+    (start, end): (NaiveDate, NaiveDate),
+) -> Result<(Vec<Vec<String>>, usize), Errcode> {
+    let settings = FecSettings::from_config(cfg)?;
+    let accounting = cfg.get_str("currency", "accounting");
+    let in_year = |day: &NaiveDate| (start..=end).contains(day);
+    let (mut sales, mut bank) = (vec![], vec![]);
+    let mut undated = 0;
+    let mut nb_lettered = 0;
+    for inp in data.invoices.history.iter() {
+        if inp.profile != *profile {
+            continue;
+        }
+        let Date::Day(created) = inp.created else {
+            undated += 1;
+            continue;
+        };
+        let payments: Vec<&Payment> = inp.payments.iter().filter(|p| in_year(&p.date)).collect();
+        if !in_year(&created) && payments.is_empty() {
+            continue;
+        }
+        let rate = conversion_rate(inp, cfg, accounting, &created)?;
+        let paid = in_year(&created)
+            && !payments.is_empty()
+            && (payments.len() == inp.payments.len())
+            && (inp.balance(None).abs() < 0.005);
+        let lettering = paid.then(|| {
+            nb_lettered += 1;
+            let last_payment = payments.iter().map(|p| p.date).max().unwrap();
+            (letter_code(nb_lettered), last_payment)
+        });
+
+        if in_year(&created) {
+            let header = EntryHeader {
+                journal: &settings.journal,
+                num: sales.len() + 1,
+                date: created,
+                piece_ref: inp.display_number(cfg),
+                piece_date: created,
+            };
+            let lines = invoice_lines(&settings, data, inp, cfg, accounting, rate, &lettering);
+            sales.push((header, lines));
+        }
+        for payment in payments {
+            let header = EntryHeader {
+                journal: &settings.bank_journal,
+                num: bank.len() + 1,
+                date: payment.date,
+                piece_ref: inp.display_number(cfg),
+                piece_date: payment.date,
+            };
+            let lines = payment_lines(&settings, data, inp, cfg, accounting, rate, payment);
+            let lines = lines.into_iter().map(|mut line| {
+                if line.aux.is_some() {
+                    line.lettering.clone_from(&lettering);
+                }
+                line
+            });
+            bank.push((header, lines.collect()));
+        }
+    }
+    let rows = sales
+        .into_iter()
+        .chain(bank)
+        .flat_map(|(header, lines)| {
+            lines
+                .into_iter()
+                .map(|line| fec_row(&settings, &header, line))
+                .collect::<Vec<Vec<String>>>()
+        })
+        .collect();
+    Ok((rows, undated))
+}
+
+/// Rate converting the amounts of the invoice to the accounting currency, the payments
+/// are converted with the rate of their invoice
+fn conversion_rate(
+    inp: &InvoiceInput,
+    cfg: &ConfigStore,
+    accounting: &str,
+    created: &NaiveDate,
+) -> Result<f64, Errcode> {
+    let currency = inp.currency_code(cfg);
+    match inp.exchange_rate.as_ref() {
+        Some(rate) => Ok(rate.rate),
+        None if currency == accounting => Ok(1.0),
+        None => Err(Errcode::ExchangeRateMissing(
+            currency,
+            accounting.to_string(),
+            created.to_string(),
+        )),
+    }
+}
+
+/// Letters A to Z, then AA, AB...
+fn letter_code(mut nb: usize) -> String {
+    let mut code = vec![];
+    while nb > 0 {
+        nb -= 1;
+        code.push((b'A' + (nb % 26) as u8) as char);
+        nb /= 26;
+    }
+    code.into_iter().rev().collect()
+}
+
+fn round(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Auxiliary account and name of the customer of the invoice
+fn customer_aux(settings: &FecSettings, data: &Datastore, inp: &InvoiceInput) -> (String, String) {
+    let name = inp
+        .snapshot
+        .as_ref()
+        .map(|snap| snap.recipient.name.clone())
+        .or_else(|| {
+            data.contacts
+                .contains(&inp.recipient)
+                .then(|| data.contacts.get(&inp.recipient).name.clone())
+        })
+        .unwrap_or_else(|| inp.recipient.clone());
+    let aux_num = format!(
+        "{}{}",
+        settings.customer_aux_prefix,
+        inp.recipient.to_uppercase()
+    );
+    (aux_num, name)
+}
+
+fn invoice_lines<'a>(
+    settings: &'a FecSettings,
+    data: &Datastore,
+    inp: &InvoiceInput,
+    cfg: &ConfigStore,
+    accounting: &str,
+    rate: f64,
+    lettering: &Option<(String, NaiveDate)>,
+) -> Vec<EntryLine<'a>> {
+    let currency = inp.currency_code(cfg);
+    let foreign = |amount: f64| (currency != accounting).then(|| (round(amount), currency.clone()));
+
+    let total = inp.total_no_tax();
+    let tax = total * inp.tax_rate.unwrap_or(0.0);
+    // The amount due by the customer is the sum of the rounded lines, so the entry balances
+    let (total_conv, tax_conv) = (round(total * rate), round(tax * rate));
+
+    let (aux_num, name) = customer_aux(settings, data, inp);
+    let label = format!("Facture {} {name}", inp.display_number(cfg));
+    let mut lines = vec![
+        EntryLine {
+            account: &settings.customer,
+            aux: Some((aux_num, name)),
+            label: label.clone(),
+            amount: total_conv + tax_conv,
+            foreign: foreign(total + tax),
+            lettering: lettering.clone(),
+        },
+        EntryLine {
+            account: &settings.revenue,
+            aux: None,
+            label: label.clone(),
+            amount: -total_conv,
+            foreign: foreign(total),
+            lettering: None,
+        },
+    ];
+    if tax_conv != 0.0 {
+        lines.push(EntryLine {
+            account: &settings.vat,
+            aux: None,
+            label,
+            amount: -tax_conv,
+            foreign: foreign(tax),
+            lettering: None,
+        });
+    }
+    lines
+}
+
+/// Payment received on the bank account, settling the amount due by the customer
+fn payment_lines<'a>(
+    settings: &'a FecSettings,
+    data: &Datastore,
+    inp: &InvoiceInput,
+    cfg: &ConfigStore,
+    accounting: &str,
+    rate: f64,
+    payment: &Payment,
+) -> Vec<EntryLine<'a>> {
+    let currency = inp.currency_code(cfg);
+    let foreign = (currency != accounting).then(|| (round(payment.amount), currency.clone()));
+    let amount = round(payment.amount * rate);
+    let (aux_num, name) = customer_aux(settings, data, inp);
+    let label = format!("Règlement {} {name}", inp.display_number(cfg));
+    vec![
+        EntryLine {
+            account: &settings.bank,
+            aux: None,
+            label: label.clone(),
+            amount,
+            foreign: foreign.clone(),
+            lettering: None,
+        },
+        EntryLine {
+            account: &settings.customer,
+            aux: Some((aux_num, name)),
+            label,
+            amount: -amount,
+            foreign,
+            lettering: None,
+        },
+    ]
+}
+
+fn fec_amount(amount: f64) -> String {
+    // Adding zero turns -0.0 into 0.0
+    format!("{:.2}", amount + 0.0).replace('.', ",")
+}
+
+fn fec_row(settings: &FecSettings, header: &EntryHeader, line: EntryLine) -> Vec<String> {
+    let fec_date = |date: &NaiveDate| date.format("%Y%m%d").to_string();
+    let (aux_num, aux_name) = line.aux.unwrap_or_default();
+    let (debit, credit) = if line.amount >= 0.0 {
+        (line.amount, 0.0)
+    } else {
+        (0.0, -line.amount)
+    };
+    let (foreign_amount, foreign_code) = match line.foreign {
+        Some((amount, code)) => (fec_amount(amount.abs()), code),
+        None => ("".to_string(), "".to_string()),
+    };
+    let (letter, letter_date) = match line.lettering {
+        Some((letter, date)) => (letter, fec_date(&date)),
+        None => ("".to_string(), "".to_string()),
+    };
+    let row = [
+        header.journal.0.clone(),
+        header.journal.1.clone(),
+        header.num.to_string(),
+        fec_date(&header.date),
+        line.account.0.clone(),
+        line.account.1.clone(),
+        aux_num,
+        aux_name,
+        header.piece_ref.clone(),
+        fec_date(&header.piece_date),
+        line.label,
+        fec_amount(debit),
+        fec_amount(credit),
+        letter,
+        letter_date,
+        fec_date(&header.date),
+        foreign_amount,
+        foreign_code,
+    ];
+    // The separator cannot be used in the fields
+    row.iter()
+        .map(|field| field.replace([settings.separator, '\n', '\r'], " "))
+        .collect()
+}
+
+pub fn write_fec(cfg: &ConfigStore, rows: &[Vec<String>]) -> Result<String, Errcode> {
+    let sep = FecSettings::from_config(cfg)?.separator.to_string();
+    let mut res = FEC_COLUMNS.join(&sep) + "\r\n";
+    for row in rows.iter() {
+        res += &row.join(&sep);
+        res += "\r\n";
+    }
+    Ok(res)
+}
+
+#[test]
+fn fec_export() {
+    use crate::contact::ContactBook;
+    use crate::doctype::quotation::QuotationSavedData;
+    use crate::rates::RateTable;
+
+    let data = Datastore {
+        contacts: ContactBook::default(),
+        invoices: serde_json::from_str(
+            r#"{"id_counter": 5, "history": [
+            {"id": 1, "recipient": "acme", "quote_nb": null, "date_sell": "2025-12-20",
+             "tx": [["Audit", 1.0, 1000.0]], "tax_rate": 0.2, "created": "2025-12-31",
+             "payments": [{"date": "2026-01-10", "amount": 1200.0}]},
+            {"id": 2, "recipient": "initech", "quote_nb": null, "date_sell": "2026-01-02",
+             "tx": [["Support", 1.0, 333.33]], "tax_rate": 0.2, "created": "2026-01-05",
+             "currency": "USD", "payments": [{"date": "2026-02-01", "amount": 400.0}],
+             "exchange_rate": {"currency": "EUR", "rate": 0.9, "date": "2026-01-05"}},
+            {"id": 3, "recipient": "acme", "quote_nb": null, "date_sell": "2026-03-01",
+             "tx": [["Refund", 1.0, -200.0]], "tax_rate": null, "created": "2026-03-30"},
+            {"id": 4, "recipient": "acme", "quote_nb": null, "date_sell": "2027-01-01",
+             "tx": [["Audit", 1.0, 50.0]], "tax_rate": null, "created": "2027-01-01"}]}"#,
+        )
+        .unwrap(),
+        quotations: QuotationSavedData::init(),
+        audit: vec![],
+        rates: RateTable::default(),
+    };
+    let cfg: ConfigStore = toml::from_str(&include_str!("../default/config.toml").replace(
+        "siret_number = \"\"",
+        "siret_number = \"732 829 320 00074\"",
+    ))
+    .unwrap();
+    let year = fiscal_year(&cfg, 2026).unwrap();
+    assert_eq!(year.0, NaiveDate::from_ymd_opt(2026, 1, 1).unwrap());
+    assert_eq!(
+        fec_fname(&cfg, &year.1).unwrap(),
+        "732829320FEC20261231.txt"
+    );
+
+    let (rows, undated) = fec_entries(&cfg, &data, &None, year).unwrap();
+    assert_eq!(undated, 0);
+    // Invoice 2 has a VAT line, the refund has none, then the payments of invoices 1 and 2
+    assert_eq!(rows.len(), 9);
+    assert!(rows.iter().all(|row| row.len() == FEC_COLUMNS.len()));
+    let amounts = |row: &Vec<String>| -> f64 {
+        let parse = |s: &String| s.replace(',', ".").parse::<f64>().unwrap();
+        parse(&row[11]) - parse(&row[12])
+    };
+    assert!(rows.iter().map(amounts).sum::<f64>().abs() < 1e-9);
+    assert_eq!(rows[0][4..8], ["411000", "Clients", "CINITECH", "initech"]);
+    assert_eq!(rows[0][11], "360,00");
+    assert_eq!(rows[0][16..], ["400,00", "USD"]);
+    assert_eq!(rows[3][2], "2");
+    assert_eq!(rows[3][11..13], ["0,00", "200,00"]);
+    assert_eq!(rows[4][11..13], ["200,00", "0,00"]);
+
+    // Invoice 2 is paid within the year, the first payment is for an invoice of last year
+    assert_eq!(rows[0][13..15], ["A", "20260201"]);
+    assert_eq!(rows[1][13..15], ["", ""]);
+    assert_eq!(
+        rows[5][..5],
+        ["BQ", "Journal de banque", "1", "20260110", "512000"]
+    );
+    assert_eq!(rows[5][11], "1200,00");
+    assert_eq!(rows[6][4..7], ["411000", "Clients", "CACME"]);
+    assert_eq!(rows[6][12..15], ["1200,00", "", ""]);
+    assert_eq!(rows[8][2], "2");
+    assert_eq!(
+        rows[8][12..],
+        ["360,00", "A", "20260201", "20260201", "400,00", "USD"]
+    );
+    assert_eq!(letter_code(28), "AB");
+    assert!(write_fec(&cfg, &rows)
+        .unwrap()
+        .starts_with("JournalCode|JournalLib|"));
+}
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+
+use crate::config::ConfigStore;
+use crate::errors::Errcode;
+
+/// Values that can be used in the file name template of a document
+pub struct FileNameFields<'a> {
+    pub doctype: &'a str,
+    pub number: &'a str,
+    pub recipient: &'a str,
+    pub recipient_name: &'a str,
+    pub status: &'a str,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Replaces everything that can't safely be part of a file name on any platform
+pub fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() || c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn expand_token(token: &str, fields: &FileNameFields) -> Result<String, Errcode> {
+    let invalid = |msg: String| Errcode::InvalidConfig("filename", msg);
+    let value = match token {
+        "doctype" => fields.doctype.to_string(),
+        "number" => fields.number.to_string(),
+        "recipient" => fields.recipient.to_string(),
+        "recipient_name" => fields.recipient_name.to_string(),
+        "status" => fields.status.to_string(),
+        "date" => fields.created_at.format("%d%m%y").to_string(),
+        t => match t.strip_prefix("date:") {
+            Some(fmt) => {
+                let items = StrftimeItems::new(fmt).collect::<Vec<Item>>();
+                if items.iter().any(|i| matches!(i, Item::Error)) {
+                    return Err(invalid(format!("Invalid date format {fmt:?}")));
+                }
+                fields
+                    .created_at
+                    .format_with_items(items.into_iter())
+                    .to_string()
+            }
+            None => return Err(invalid(format!("Unknown token {t:?}"))),
+        },
+    };
+    Ok(sanitize(&value))
+}
+
+/// Builds the path of a document relative to the output directory.
+/// Each `/` of the template creates a sub-directory, tokens can't create any.
+pub fn file_name(template: &str, fields: &FileNameFields) -> Result<PathBuf, Errcode> {
+    let invalid = |msg: String| Errcode::InvalidConfig("filename", msg);
+    let mut path = PathBuf::new();
+    for part in template.split('/') {
+        let mut component = String::new();
+        let mut rest = part;
+        while let Some(start) = rest.find('{') {
+            component += &sanitize(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid(format!("Unclosed token in {template:?}")))?;
+            component += &expand_token(&rest[start + 1..start + end], fields)?;
+            rest = &rest[start + end + 1..];
+        }
+        component += &sanitize(rest);
+
+        let component = component.trim_matches(|c| (c == '.') || (c == ' '));
+        if component.is_empty() {
+            return Err(invalid(format!(
+                "Template {template:?} gives an empty path component"
+            )));
+        }
+        path.push(component);
+    }
+    if path.extension().map(|e| e != "pdf").unwrap_or(true) {
+        let fname = path.file_name().unwrap().to_string_lossy().to_string();
+        path.set_file_name(fname + ".pdf");
+    }
+    Ok(path)
+}
+
+/// What to do when the output file already exists
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CollisionPolicy {
+    Rename,
+    Overwrite,
+    Fail,
+}
+
+impl CollisionPolicy {
+    pub fn from_config(cfg: &ConfigStore) -> Result<CollisionPolicy, Errcode> {
+        match cfg.get_str("output", "on_collision") {
+            "rename" => Ok(CollisionPolicy::Rename),
+            "overwrite" => Ok(CollisionPolicy::Overwrite),
+            "fail" => Ok(CollisionPolicy::Fail),
+            p => Err(Errcode::InvalidConfig(
+                "output",
+                format!("Unknown collision policy {p:?}"),
+            )),
+        }
+    }
+
+    /// Gives the path to write to, `file.pdf` becomes `file_1.pdf`, `file_2.pdf`, ...
+    pub fn resolve(&self, path: &Path) -> Result<PathBuf, Errcode> {
+        if !path.exists() {
+            return Ok(path.to_path_buf());
+        }
+        match self {
+            CollisionPolicy::Overwrite => Ok(path.to_path_buf()),
+            CollisionPolicy::Fail => Err(Errcode::OutputFileExists(path.display().to_string())),
+            CollisionPolicy::Rename => {
+                let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+                let ext = path.extension().unwrap_or_default().to_string_lossy();
+                let mut n = 1;
+                loop {
+                    let candidate = path.with_file_name(format!("{stem}_{n}.{ext}"));
+                    if !candidate.exists() {
+                        return Ok(candidate);
+                    }
+                    n += 1;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn file_name_templates() {
+    use chrono::TimeZone;
+    let fields = FileNameFields {
+        doctype: "invoice",
+        number: "F-2026/0001",
+        recipient: "acme",
+        recipient_name: "ACME: Corp.",
+        status: "issued",
+        created_at: Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap(),
+    };
+    let fname = |t: &str| file_name(t, &fields).map(|p| p.display().to_string());
+
+    assert_eq!(
+        fname("{doctype}_{recipient}_{number}_{date}.pdf").unwrap(),
+        "invoice_acme_F-2026_0001_191026.pdf"
+    );
+    assert_eq!(
+        fname("{date:%Y}/{date:%m}/{recipient_name} {status}").unwrap(),
+        "2026/10/ACME__Corp._issued.pdf"
+    );
+    assert!(fname("../{number}.pdf").is_err());
+    assert!(fname("{customer}.pdf").is_err());
+    assert!(fname("{date:%Q}.pdf").is_err());
+
+    let dir = std::env::temp_dir().join(format!("docgen-fname-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("invoice.pdf");
+    std::fs::write(&path, "").unwrap();
+    assert_eq!(
+        CollisionPolicy::Rename.resolve(&path).unwrap(),
+        dir.join("invoice_1.pdf")
+    );
+    assert_eq!(CollisionPolicy::Overwrite.resolve(&path).unwrap(), path);
+    assert!(CollisionPolicy::Fail.resolve(&path).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,370 @@
+use crate::config::ConfigStore;
+use crate::doctype::snapshot::QrBillSettings;
+use crate::errors::Errcode;
+use crate::qr_bill;
+
+/// Values of the SIRET number, IBAN and BIC in the default configuration of older versions,
+/// they are not checked like the settings left empty
+const PLACEHOLDERS: [&str; 3] = ["010203040506070809", "IBAN", "BIC"];
+
+/// Length of the IBANs of the most common countries, the others are only checked with
+/// their check digits
+const IBAN_LENGTHS: [(&str, usize); 20] = [
+    ("AT", 20),
+    ("BE", 16),
+    ("CH", 21),
+    ("DE", 22),
+    ("DK", 18),
+    ("ES", 24),
+    ("FI", 18),
+    ("FR", 27),
+    ("GB", 22),
+    ("GR", 27),
+    ("IE", 22),
+    ("IT", 27),
+    ("LI", 21),
+    ("LU", 20),
+    ("MC", 27),
+    ("NL", 18),
+    ("NO", 15),
+    ("PL", 28),
+    ("PT", 25),
+    ("SE", 24),
+];
+
+/// Formats of the EU VAT numbers after the country code: 9 is a digit, A a letter,
+/// X a digit or a letter, any other character is written as is
+const VAT_FORMATS: [(&str, &[&str]); 27] = [
+    ("AT", &["U99999999"]),
+    ("BE", &["9999999999"]),
+    ("BG", &["999999999", "9999999999"]),
+    ("CY", &["99999999A"]),
+    ("CZ", &["99999999", "999999999", "9999999999"]),
+    ("DE", &["999999999"]),
+    ("DK", &["99999999"]),
+    ("EE", &["999999999"]),
+    ("EL", &["999999999"]),
+    ("ES", &["X9999999X"]),
+    ("FI", &["99999999"]),
+    ("FR", &["XX999999999"]),
+    ("HR", &["99999999999"]),
+    ("HU", &["99999999"]),
+    ("IE", &["9999999A", "9X99999A", "9999999AA"]),
+    ("IT", &["99999999999"]),
+    ("LT", &["999999999", "999999999999"]),
+    ("LU", &["99999999"]),
+    ("LV", &["99999999999"]),
+    ("MT", &["99999999"]),
+    ("NL", &["999999999B99"]),
+    ("PL", &["9999999999"]),
+    ("PT", &["999999999"]),
+    (
+        "RO",
+        &[
+            "99",
+            "999",
+            "9999",
+            "99999",
+            "999999",
+            "9999999",
+            "99999999",
+            "999999999",
+            "9999999999",
+        ],
+    ),
+    ("SE", &["999999999901"]),
+    ("SI", &["99999999"]),
+    ("SK", &["9999999999"]),
+];
+
+/// Check of a setting, with the reason why it is invalid
+type Check = fn(&str) -> Result<(), String>;
+
+/// Letters and digits of an identifier, in upper case
+pub fn compact(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Remainder by 97 of an alphanumeric code, the letters counting as 10 to 35
+pub fn mod97(code: &str) -> u32 {
+    code.chars().fold(0, |rem, c| {
+        let value = c.to_digit(36).unwrap_or(0);
+        if value < 10 {
+            (rem * 10 + value) % 97
+        } else {
+            (rem * 100 + value) % 97
+        }
+    })
+}
+
+fn luhn(digits: &str) -> bool {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(idx, d)| match (idx % 2, d * 2) {
+            (0, _) => d,
+            (_, double) if double > 9 => double - 9,
+            (_, double) => double,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn all_digits(text: &str, len: usize) -> bool {
+    (text.len() == len) && text.chars().all(|c| c.is_ascii_digit())
+}
+
+fn matches_format(text: &str, format: &str) -> bool {
+    (text.len() == format.len())
+        && text.chars().zip(format.chars()).all(|(c, f)| match f {
+            '9' => c.is_ascii_digit(),
+            'A' => c.is_ascii_uppercase(),
+            'X' => c.is_ascii_alphanumeric(),
+            _ => c == f,
+        })
+}
+
+pub fn check_iban(iban: &str) -> Result<(), String> {
+    let iban = compact(iban);
+    let (country, check) = (iban.get(..2).unwrap_or(""), iban.get(2..4).unwrap_or(""));
+    if !country.chars().all(|c| c.is_ascii_uppercase()) || !all_digits(check, 2) {
+        return Err("an IBAN starts with a country code and 2 check digits".to_string());
+    }
+    let expected = IBAN_LENGTHS
+        .iter()
+        .find(|(c, _)| *c == country)
+        .map(|(_, len)| *len);
+    match expected {
+        Some(len) if iban.len() != len => {
+            return Err(format!("the IBANs of {country} have {len} characters"))
+        }
+        None if !(15..=34).contains(&iban.len()) => {
+            return Err("an IBAN has from 15 to 34 characters".to_string())
+        }
+        _ => {}
+    }
+    if mod97(&format!("{}{}", &iban[4..], &iban[..4])) != 1 {
+        return Err("wrong check digits, there is a typo in it".to_string());
+    }
+    Ok(())
+}
+
+pub fn check_bic(bic: &str) -> Result<(), String> {
+    let bic = bic.trim().to_ascii_uppercase();
+    if !matches_format(&bic, "AAAAAAXX") && !matches_format(&bic, "AAAAAAXXXXX") {
+        return Err(
+            "a BIC has 8 or 11 characters: bank, country and location codes, then the branch"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+pub fn check_siren(siren: &str) -> Result<(), String> {
+    let siren = compact(siren);
+    if !all_digits(&siren, 9) {
+        return Err("a SIREN number has 9 digits".to_string());
+    }
+    if !luhn(&siren) {
+        return Err("wrong check digit, there is a typo in it".to_string());
+    }
+    Ok(())
+}
+
+pub fn check_siret(siret: &str) -> Result<(), String> {
+    let siret = compact(siret);
+    if !all_digits(&siret, 14) {
+        return Err("a SIRET number has 14 digits".to_string());
+    }
+    // The establishments of La Poste have their own check
+    let valid = if siret.starts_with("356000000") {
+        siret
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .sum::<u32>()
+            .is_multiple_of(5)
+    } else {
+        luhn(&siret)
+    };
+    if !valid {
+        return Err("wrong check digit, there is a typo in it".to_string());
+    }
+    Ok(())
+}
+
+/// Format of an EU VAT number, and the key of the French ones
+pub fn check_vat_number(vat: &str) -> Result<(), String> {
+    let vat = compact(vat);
+    let (country, number) = vat.split_at(vat.len().min(2));
+    let Some((_, formats)) = VAT_FORMATS.iter().find(|(c, _)| *c == country) else {
+        return Err(format!(
+            "unknown country code {country:?}, it starts with the code of an EU country"
+        ));
+    };
+    if !formats.iter().any(|f| matches_format(number, f)) {
+        return Err(format!(
+            "the VAT numbers of {country} are written {country}{}",
+            formats.join(" or ")
+        ));
+    }
+    if country == "FR" {
+        let (key, siren) = number.split_at(2);
+        check_siren(siren)?;
+        // The keys with letters of the new companies are not computed from the SIREN
+        if let (Ok(key), Ok(siren)) = (key.parse::<u64>(), siren.parse::<u64>()) {
+            if key != (12 + 3 * (siren % 97)) % 97 {
+                return Err("the key does not match the SIREN number".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the bank account, the SIRET and VAT numbers of the configuration and of its
+/// issuer profiles, the settings left empty are not checked, and the settings of the
+/// QR-bills when they are enabled
+pub fn check_config(cfg: &ConfigStore) -> Result<(), Errcode> {
+    check_settings(cfg, "")?;
+    for profile in cfg.profiles() {
+        check_settings(
+            &cfg.for_profile(&profile)?,
+            &format!(" of the profile {profile}"),
+        )?;
+    }
+    Ok(())
+}
+
+fn check_settings(cfg: &ConfigStore, origin: &str) -> Result<(), Errcode> {
+    let checks: [(&'static str, &str, Check); 5] = [
+        ("bank", "iban", check_iban),
+        ("bank", "bic", check_bic),
+        ("company", "siret_number", check_siret),
+        ("company", "vat_number", check_vat_number),
+        ("qr_bill", "iban", check_iban),
+    ];
+    for (section, key, check) in checks {
+        let value = cfg.get_str(section, key);
+        if value.trim().is_empty() || PLACEHOLDERS.contains(&value) {
+            continue;
+        }
+        if let Err(reason) = check(value) {
+            return Err(Errcode::InvalidConfig(
+                section,
+                format!("{key}{origin} {value:?} is invalid, {reason}"),
+            ));
+        }
+    }
+    if cfg.get_bool("qr_bill", "add_qr_bill") {
+        let settings = QrBillSettings::from_config(cfg);
+        qr_bill::check_settings(&settings, cfg.get_str("bank", "iban")).map_err(|reason| {
+            Errcode::InvalidConfig(
+                "qr_bill",
+                format!("add_qr_bill is set{origin}, but {reason}"),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+#[test]
+fn identifier_checks() {
+    assert!(check_iban("FR76 3000 6000 0112 3456 7890 189").is_ok());
+    assert!(check_iban("gb82west12345698765432").is_ok());
+    assert!(check_iban("CH93 0076 2011 6238 5295 7").is_ok());
+    assert!(check_iban("FR76 3000 6000 0112 3456 7890 198").is_err());
+    assert!(check_iban("FR76 3000 6000 0112 3456 7890").is_err());
+    assert!(check_iban("IBAN").is_err());
+
+    assert!(check_bic("DEUTDEFF").is_ok());
+    assert!(check_bic("agrifrpp882").is_ok());
+    assert!(check_bic("DEUT DEFF").is_err());
+    assert!(check_bic("1GRIFRPP").is_err());
+
+    assert!(check_siren("732 829 320").is_ok());
+    assert!(check_siren("732 829 321").is_err());
+    assert!(check_siret("732 829 320 00074").is_ok());
+    assert!(check_siret("732 829 320 00075").is_err());
+    assert!(check_siret("010203040506070809").is_err());
+    // Not a valid Luhn number, but the digits of La Poste add up to a multiple of 5
+    assert!(check_siret("35600000049837").is_ok());
+
+    assert!(check_vat_number("FR44 732829320").is_ok());
+    assert!(check_vat_number("FR45 732829320").is_err());
+    assert!(check_vat_number("FRXA 732829320").is_ok());
+    assert!(check_vat_number("DE123456789").is_ok());
+    assert!(check_vat_number("NL123456789B01").is_ok());
+    assert!(check_vat_number("NL123456789").is_err());
+    assert!(check_vat_number("US123456789").is_err());
+
+    let default_cfg = include_str!("../default/config.toml");
+    let cfg: ConfigStore = toml::from_str(default_cfg).unwrap();
+    assert!(check_config(&cfg).is_ok());
+    // Roots created with the default configuration of older versions
+    let baseline = default_cfg
+        .replace(
+            "siret_number = \"\"",
+            "siret_number = \"010203040506070809\"",
+        )
+        .replace("iban = \"\"\nbic = \"\"", "iban = \"IBAN\"\nbic = \"BIC\"");
+    assert!(baseline.contains("\"IBAN\""));
+    let cfg: ConfigStore = toml::from_str(&baseline).unwrap();
+    assert!(check_config(&cfg).is_ok());
+    let cfg: ConfigStore = toml::from_str(
+        &default_cfg.replace("vat_number = \"\"", "vat_number = \"FR45 732829320\""),
+    )
+    .unwrap();
+    let Err(Errcode::InvalidConfig("company", msg)) = check_config(&cfg) else {
+        panic!("The VAT number of the company is not checked");
+    };
+    assert!(msg.starts_with("vat_number"));
+    let cfg: ConfigStore = toml::from_str(
+        r#"
+        [company]
+        siret_number = ""
+        vat_number = ""
+        [bank]
+        iban = "FR76 3000 6000 0112 3456 7890 189"
+        bic = ""
+        [qr_bill]
+        add_qr_bill = false
+        iban = ""
+        [profiles.freelance.bank]
+        iban = "FR76 3000 6000 0112 3456 7890 198"
+        "#,
+    )
+    .unwrap();
+    let Err(Errcode::InvalidConfig(section, msg)) = check_config(&cfg) else {
+        panic!("The IBAN of the profile is not checked");
+    };
+    assert_eq!(section, "bank");
+    assert!(msg.starts_with("iban of the profile freelance"));
+
+    let cfg: ConfigStore = toml::from_str(
+        r#"
+        [company]
+        siret_number = ""
+        vat_number = ""
+        [bank]
+        iban = "FR76 3000 6000 0112 3456 7890 189"
+        bic = ""
+        [qr_bill]
+        add_qr_bill = true
+        iban = ""
+        street = ""
+        building_number = ""
+        postcode = "2501"
+        town = "Biel"
+        country = "CH"
+        "#,
+    )
+    .unwrap();
+    let Err(Errcode::InvalidConfig("qr_bill", msg)) = check_config(&cfg) else {
+        panic!("The QR-bills of a French account are accepted");
+    };
+    assert!(msg.contains("not a Swiss or Liechtenstein account"));
+}
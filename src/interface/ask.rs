@@ -2,6 +2,9 @@ use std::fmt::Display;
 use std::io::Write;
 use std::str::FromStr;
 
+use chrono::NaiveDate;
+
+use crate::currency::Currency;
 use crate::data::Transaction;
 use crate::lang::LangDict;
 
@@ -47,6 +50,24 @@ pub fn ask_user_nonempty<T: Display>(question: T) -> String {
     res.trim().to_string()
 }
 
+/// Asks for an ISO 4217 currency code, an empty answer keeps the default one
+pub fn ask_currency(default: &str) -> String {
+    ask_user_parse::<_, Currency>(format!("Currency [{default}]: "))
+        .map(|c| c.code)
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Asks for a date until it can be understood
+pub fn ask_date<T: Display>(question: T, lang: &LangDict) -> NaiveDate {
+    loop {
+        let res = ask_user_nonempty(&question);
+        if let Some(date) = lang.parse_date(&res) {
+            return date;
+        }
+        println!("Unable to understand the date, write it as 2024-03-21 or 21/03/2024");
+    }
+}
+
 pub fn ask_for_transactions(lang: &LangDict) -> Vec<Transaction> {
     let desc = lang.get_doctype_word("general", "tx_item_description");
     let units = lang.get_doctype_word("general", "tx_units");
@@ -7,8 +7,10 @@ use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 
 pub mod ask;
+mod review_matches;
 mod select_list;
 
+pub use review_matches::review_matches;
 pub use select_list::select_from_list;
 
 use crate::errors::Errcode;
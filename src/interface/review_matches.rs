@@ -0,0 +1,98 @@
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, List, ListDirection, ListState, Paragraph};
+use ratatui::Frame;
+
+use crate::errors::Errcode;
+use crate::lang::LangDict;
+use crate::reconcile::{update_balances, Proposal};
+
+use super::{enter_tui_screen, get_keyboard_events, quit_tui_screen, Tuiterm};
+
+const HELP: &str = "Up/Down: credit  Left/Right: invoice  Space: none  Enter: record  Esc: cancel";
+
+pub struct ReviewMatches<'a> {
+    proposals: &'a mut [Proposal],
+    lines: Vec<String>,
+    state: ListState,
+}
+
+impl<'a> ReviewMatches<'a> {
+    fn init(proposals: &'a mut [Proposal], lang: &LangDict) -> Result<ReviewMatches<'a>, Errcode> {
+        let lines = proposals
+            .iter()
+            .map(|p| p.display(lang))
+            .collect::<Result<Vec<String>, Errcode>>()?;
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Ok(ReviewMatches {
+            proposals,
+            lines,
+            state,
+        })
+    }
+
+    fn ui(&mut self, frame: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(1)])
+            .split(frame.size());
+        let list = List::new(self.lines.clone())
+            .block(Block::default().title("Matches").borders(Borders::ALL))
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().add_modifier(Modifier::ITALIC))
+            .highlight_symbol(">>")
+            .direction(ListDirection::TopToBottom);
+        frame.render_stateful_widget(list, layout[0], &mut self.state);
+        frame.render_widget(Paragraph::new(HELP), layout[1]);
+    }
+
+    /// Returns whether the review is over, and if the matches are accepted
+    fn handle_events(&mut self, lang: &LangDict) -> Result<Option<bool>, Errcode> {
+        let Some(Event::Key(key)) = get_keyboard_events()? else {
+            return Ok(None);
+        };
+        if key.kind != KeyEventKind::Press {
+            return Ok(None);
+        }
+        let idx = self.state.selected().unwrap();
+        match key.code {
+            KeyCode::Enter => return Ok(Some(true)),
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(Some(false)),
+            KeyCode::Up => self.state.select(Some(idx.saturating_sub(1))),
+            KeyCode::Down => self.state.select(Some((idx + 1).min(self.lines.len() - 1))),
+            KeyCode::Right => self.proposals[idx].next_candidate(),
+            KeyCode::Left => self.proposals[idx].previous_candidate(),
+            KeyCode::Char(' ') => self.proposals[idx].selected = None,
+            _ => {}
+        }
+        // Changing the invoice paid by a credit changes the balances of the next ones
+        update_balances(self.proposals);
+        for (line, proposal) in self.lines.iter_mut().zip(self.proposals.iter()) {
+            *line = proposal.display(lang)?;
+        }
+        Ok(None)
+    }
+
+    pub fn exec(&mut self, terminal: &mut Tuiterm, lang: &LangDict) -> Result<bool, Errcode> {
+        loop {
+            terminal.draw(|frame| self.ui(frame))?;
+            if let Some(accepted) = self.handle_events(lang)? {
+                return Ok(accepted);
+            }
+        }
+    }
+}
+
+/// Lets the user change the invoice matched with each credit, returns false if cancelled
+pub fn review_matches(proposals: &mut [Proposal], lang: &LangDict) -> Result<bool, Errcode> {
+    if proposals.is_empty() {
+        return Ok(false);
+    }
+    let mut widget = ReviewMatches::init(proposals, lang)?;
+    let mut terminal = enter_tui_screen()?;
+    let res = widget.exec(&mut terminal, lang);
+    quit_tui_screen()?;
+    res
+}
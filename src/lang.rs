@@ -1,22 +1,93 @@
-use chrono::{DateTime, Datelike, Utc};
-use std::path::PathBuf;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Datelike, NaiveDate};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 
+use crate::config::ConfigStore;
 use crate::errors::Errcode;
 
+type LangTable = toml::map::Map<String, toml::Value>;
+
+/// Language packs shipped with the program
+const BUILTIN_LANGS: &[(&str, &str)] = &[
+    ("en", include_str!("../default/lang/en.toml")),
+    ("fr", include_str!("../default/lang/fr.toml")),
+    ("de", include_str!("../default/lang/de.toml")),
+    ("es", include_str!("../default/lang/es.toml")),
+];
+
 pub struct LangDict {
-    data: toml::map::Map<String, toml::Value>,
+    data: LangTable,
 }
 impl LangDict {
-    pub fn get_date_fmt(&self, date: &DateTime<Utc>) -> String {
-        let month_list = self.data.get("months").unwrap().as_array().unwrap();
-        let month_list: Vec<&str> = month_list.iter().map(|v| v.as_str().unwrap()).collect();
-        let month_idx: usize = date.month().try_into().unwrap();
-        format!(
-            "{} {} {}",
-            date.day(),
-            month_list.get(month_idx).unwrap(),
-            date.year(),
-        )
+    /// Writes a date with the pattern of the `[date]` table, chrono's strftime specifiers
+    /// are used, except the names of days and months which are taken from this language
+    pub fn format_date(&self, date: &NaiveDate) -> Result<String, Errcode> {
+        let months = self.get_list("months");
+        let days = self.get_list("days");
+        let month = &months[date.month0() as usize];
+        let day = &days[date.weekday().num_days_from_monday() as usize];
+        let abbrev = |word: &String| word.chars().take(3).collect::<String>();
+
+        let pattern = self.get_doctype_word("date", "format");
+        let mut fmt = String::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            let word = match (c, chars.clone().next()) {
+                ('%', Some('A')) => day.clone(),
+                ('%', Some('a')) => abbrev(day),
+                ('%', Some('B')) => month.clone(),
+                ('%', Some('b')) => abbrev(month),
+                ('%', Some('o')) => format!("{}{}", date.day(), self.ordinal_suffix(date.day())),
+                ('%', Some('%')) => "%".to_string(),
+                _ => {
+                    fmt.push(c);
+                    continue;
+                }
+            };
+            chars.next();
+            fmt += &word.replace('%', "%%");
+        }
+
+        let invalid = || Errcode::InvalidConfig("date", format!("Invalid date format {pattern:?}"));
+        let items: Vec<Item> = StrftimeItems::new(&fmt).collect();
+        if items.iter().any(|item| matches!(item, Item::Error)) {
+            return Err(invalid());
+        }
+        // Specifiers of a time of the day cannot be written for a date
+        let mut res = String::new();
+        write!(res, "{}", date.format_with_items(items.iter())).map_err(|_| invalid())?;
+        Ok(res)
+    }
+
+    /// Understands a date written as 2024-03-21, 21/03/2024, 21.03.2024, 21-03-2024,
+    /// or with the name of the month in this language, e.g. "21 March 2024"
+    pub fn parse_date(&self, text: &str) -> Option<NaiveDate> {
+        parse_date_with(text, &self.get_list("months"), 0)
+    }
+
+    fn ordinal_suffix(&self, day: u32) -> String {
+        self.data
+            .get("date")
+            .and_then(|table| table.get("ordinal_exceptions"))
+            .and_then(|exceptions| exceptions.get(day.to_string()))
+            .and_then(|suffix| suffix.as_str())
+            .map(|suffix| suffix.to_string())
+            .unwrap_or_else(|| self.get_doctype_word("date", "ordinal_suffix"))
+    }
+
+    fn get_list(&self, key: &str) -> Vec<String> {
+        self.data
+            .get(key)
+            .and_then(|list| list.as_array())
+            .unwrap_or_else(|| panic!("Unable to get lang list {key}"))
+            .iter()
+            .map(|word| {
+                word.as_str()
+                    .unwrap_or_else(|| panic!("Unable to convert word of {key} to String"))
+                    .to_string()
+            })
+            .collect()
     }
 
     pub fn get_doctype_word<D: ToString, W: ToString>(&self, subtable: D, word: W) -> String {
@@ -35,16 +106,233 @@ impl LangDict {
     }
 }
 
-pub fn import_lang_profile(langf: &PathBuf) -> Result<LangDict, Errcode> {
-    let data = if langf.is_file() {
-        std::fs::read_to_string(langf)?
-    } else {
-        let s = include_str!("../default/lang.toml").to_string();
-        std::fs::write(langf, &s)?;
-        s
-    };
-    let data: toml::Value = toml::from_str(&data).expect("Error in lang definition");
-    Ok(LangDict {
-        data: data.as_table().unwrap().to_owned(),
+/// Language packs of a root directory, the ones in its `lang` directory are used
+/// before the built-in ones
+pub struct Languages {
+    root: PathBuf,
+    /// Language asked for on the command line
+    requested: Option<String>,
+}
+
+impl Languages {
+    pub fn new(root: &Path, requested: Option<&str>) -> Result<Languages, Errcode> {
+        let langs = Languages {
+            root: root.to_path_buf(),
+            requested: requested.map(|l| l.to_string()),
+        };
+        if let Some(code) = requested {
+            langs.check_exists(code)?;
+        }
+        Ok(langs)
+    }
+
+    fn check_exists(&self, code: &str) -> Result<(), Errcode> {
+        let valid = !code.is_empty()
+            && code
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if valid && (builtin_lang(code).is_some() || self.user_file(code).is_file()) {
+            Ok(())
+        } else {
+            Err(Errcode::LangNotFound(code.to_string()))
+        }
+    }
+
+    fn user_file(&self, code: &str) -> PathBuf {
+        self.root.join("lang").join(code).with_extension("toml")
+    }
+
+    /// Language of a document: the one asked for, else the preferred one of the document
+    /// or its recipient, else the default one of the configuration
+    pub fn select(
+        &self,
+        cfg: &ConfigStore,
+        preferred: &Option<String>,
+    ) -> Result<(String, LangDict), Errcode> {
+        let default = cfg.get_str("lang", "default");
+        let code = self
+            .requested
+            .clone()
+            .or(preferred.clone())
+            .unwrap_or_else(|| default.to_string());
+        let lang = self.load(&code, default)?;
+        Ok((code, lang))
+    }
+
+    /// Loads a language, the words missing from it are taken from the default language
+    pub fn load(&self, code: &str, default: &str) -> Result<LangDict, Errcode> {
+        self.check_exists(code)?;
+        let mut layers = vec![code];
+        if default != code {
+            self.check_exists(default)?;
+            layers.push(default);
+        }
+
+        let mut data = LangTable::new();
+        for layer in layers {
+            let mut files = vec![self.user_file(layer)];
+            // Single lang file used before the language packs, customizing the default one
+            if layer == default {
+                files.push(self.root.join("lang.toml"));
+            }
+            for fname in files.into_iter().filter(|f| f.is_file()) {
+                let table: LangTable = toml::from_str(&std::fs::read_to_string(&fname)?)?;
+                fill_missing(&mut data, table);
+            }
+            if let Some(builtin) = builtin_lang(layer) {
+                fill_missing(&mut data, toml::from_str(builtin).unwrap());
+            }
+        }
+        Ok(LangDict { data })
+    }
+}
+
+fn parse_date_with(text: &str, months: &[String], month_offset: u32) -> Option<NaiveDate> {
+    let text = text.trim();
+    for fmt in ["%Y-%m-%d", "%d/%m/%Y", "%d.%m.%Y", "%d-%m-%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(text, fmt) {
+            return Some(date).filter(|d| d.year() >= 1000);
+        }
+    }
+
+    // Words other than the day, month and year are ignored, e.g. "le", "de", day names
+    let (mut day, mut month, mut year) = (None, None, None);
+    let words = text.split(|c: char| c.is_whitespace() || (c == ',') || (c == '.'));
+    for word in words.filter(|w| !w.is_empty()) {
+        let digits: String = word.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if digits.is_empty() {
+            let word = word.to_lowercase();
+            if let Some(idx) = months.iter().position(|m| m.to_lowercase() == word) {
+                month = Some(idx as u32 + 1);
+            }
+        } else if (digits.len() == 4) && (digits.len() == word.len()) {
+            year = digits.parse().ok();
+        } else if day.is_none() {
+            // Ordinal suffixes are ignored, e.g. 1er, 21st
+            day = digits.parse().ok();
+        }
+    }
+    NaiveDate::from_ymd_opt(year?, month?.checked_sub(month_offset)?, day?)
+}
+
+/// Reads the dates saved as text before they were stored as ISO 8601, in any built-in
+/// language. The creation dates were written with the name of the month after the real one.
+pub fn parse_legacy_date(text: &str, creation_date: bool) -> Option<NaiveDate> {
+    BUILTIN_LANGS.iter().find_map(|(_, pack)| {
+        let lang = LangDict {
+            data: toml::from_str(pack).unwrap(),
+        };
+        parse_date_with(text, &lang.get_list("months"), creation_date as u32)
     })
 }
+
+fn builtin_lang(code: &str) -> Option<&'static str> {
+    BUILTIN_LANGS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, pack)| *pack)
+}
+
+/// Adds the tables and words of `fallback` missing from `data`
+fn fill_missing(data: &mut LangTable, fallback: LangTable) {
+    for (key, val) in fallback.into_iter() {
+        match (data.get_mut(&key), val) {
+            (None, val) => {
+                data.insert(key, val);
+            }
+            (Some(toml::Value::Table(table)), toml::Value::Table(fallback_table)) => {
+                for (word, fallback_word) in fallback_table.into_iter() {
+                    table.entry(word).or_insert(fallback_word);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn lang_fallback() {
+    let root = std::env::temp_dir().join(format!("docgen_lang_{}", std::process::id()));
+    std::fs::create_dir_all(root.join("lang")).unwrap();
+    std::fs::write(
+        root.join("lang").join("it.toml"),
+        "[general]\ntax_name = \"IVA\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        root.join("lang").join("de.toml"),
+        "[invoice]\ninvoice_nb = \"Rechnung Nr.\"\n",
+    )
+    .unwrap();
+
+    let langs = Languages::new(&root, None).unwrap();
+    let it = langs.load("it", "en").unwrap();
+    assert_eq!(it.get_doctype_word("general", "tax_name"), "IVA");
+    assert_eq!(it.get_doctype_word("general", "tx_units"), "Units");
+    let de = langs.load("de", "fr").unwrap();
+    assert_eq!(de.get_doctype_word("invoice", "invoice_nb"), "Rechnung Nr.");
+    assert_eq!(de.get_doctype_word("general", "tx_units"), "Menge");
+    assert_eq!(
+        langs
+            .load("es", "es")
+            .unwrap()
+            .get_doctype_word("general", "tax_name"),
+        "IVA"
+    );
+
+    // Every built-in pack has all the words of the others
+    for (code, _) in BUILTIN_LANGS.iter() {
+        let pack: LangTable = toml::from_str(builtin_lang(code).unwrap()).unwrap();
+        for (other, _) in BUILTIN_LANGS.iter() {
+            let mut filled = pack.clone();
+            fill_missing(
+                &mut filled,
+                toml::from_str(builtin_lang(other).unwrap()).unwrap(),
+            );
+            assert_eq!(filled, pack, "{code} lacks words of {other}");
+        }
+    }
+    assert!(Languages::new(&root, Some("xx")).is_err());
+    assert!(Languages::new(&root, Some("../it")).is_err());
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn date_formats() {
+    let langs = Languages::new(&std::env::temp_dir(), None).unwrap();
+    let load = |code| langs.load(code, code).unwrap();
+    let (en, fr, de, es) = (load("en"), load("fr"), load("de"), load("es"));
+    let day = |m, d| NaiveDate::from_ymd_opt(2026, m, d).unwrap();
+
+    assert_eq!(en.format_date(&day(10, 22)).unwrap(), "October 22nd, 2026");
+    assert_eq!(en.format_date(&day(12, 11)).unwrap(), "December 11th, 2026");
+    assert_eq!(fr.format_date(&day(1, 1)).unwrap(), "1er Janvier 2026");
+    assert_eq!(fr.format_date(&day(12, 31)).unwrap(), "31 Décembre 2026");
+    assert_eq!(de.format_date(&day(3, 5)).unwrap(), "5. März 2026");
+    assert_eq!(es.format_date(&day(7, 9)).unwrap(), "9 de julio de 2026");
+
+    for text in [
+        "2026-03-21",
+        "21/03/2026",
+        "21.03.2026",
+        "21 mars 2026",
+        "Samedi 21 Mars, 2026",
+    ] {
+        assert_eq!(fr.parse_date(text), Some(day(3, 21)), "{text}");
+    }
+    assert_eq!(en.parse_date("March 21st, 2026"), Some(day(3, 21)));
+    assert_eq!(en.parse_date("31/02/2026"), None);
+    assert_eq!(en.parse_date("21/03/26"), None);
+    assert_eq!(en.parse_date("next week"), None);
+
+    // The creation dates saved as text have the month after the real one
+    assert_eq!(
+        parse_legacy_date("4 Avril 2024", true),
+        NaiveDate::from_ymd_opt(2024, 3, 4)
+    );
+    assert_eq!(
+        parse_legacy_date("4 Avril 2024", false),
+        NaiveDate::from_ymd_opt(2024, 4, 4)
+    );
+    assert_eq!(parse_legacy_date("when it was sold", false), None);
+}
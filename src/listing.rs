@@ -0,0 +1,314 @@
+use std::cmp::Ordering;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::config::ConfigStore;
+use crate::currency::AmountFormat;
+use crate::data::{Datastore, Date};
+use crate::doctype::DocumentType;
+use crate::errors::Errcode;
+use crate::lang::LangDict;
+
+/// A document of any type, as shown in the listings
+#[derive(Serialize, Clone)]
+pub struct DocumentSummary {
+    pub doctype: String,
+    pub id: usize,
+    pub number: String,
+    pub recipient: String,
+    pub profile: Option<String>,
+    pub created: Date,
+    pub total_no_tax: f64,
+    pub currency: String,
+    /// "issued" for the invoices, "pending" or "invoiced" for the quotations
+    pub status: String,
+    pub descriptions: Vec<String>,
+}
+
+/// All the documents of a type, or of every type, in the order they were created
+pub fn summaries(
+    cfg: &ConfigStore,
+    data: &Datastore,
+    doctype: Option<DocumentType>,
+) -> Vec<DocumentSummary> {
+    let mut res = vec![];
+    if doctype.unwrap_or(DocumentType::Invoice) == DocumentType::Invoice {
+        res.extend(data.invoices.history.iter().map(|inp| DocumentSummary {
+            doctype: DocumentType::Invoice.to_string(),
+            id: inp.id,
+            number: inp.display_number(cfg),
+            recipient: inp.recipient.clone(),
+            profile: inp.profile.clone(),
+            created: inp.created.clone(),
+            total_no_tax: inp.total_no_tax(),
+            currency: inp.currency_code(cfg),
+            status: "issued".to_string(),
+            descriptions: inp.tx.iter().map(|(descr, ..)| descr.clone()).collect(),
+        }));
+    }
+    if doctype.unwrap_or(DocumentType::Quotation) == DocumentType::Quotation {
+        let mut quotes: Vec<DocumentSummary> = data
+            .quotations
+            .history
+            .values()
+            .flat_map(|quotes| quotes.iter())
+            .map(|(inp, invoice)| DocumentSummary {
+                doctype: DocumentType::Quotation.to_string(),
+                id: inp.id,
+                number: inp.display_number(cfg),
+                recipient: inp.recipient.clone(),
+                profile: inp.profile.clone(),
+                created: inp.created.clone(),
+                total_no_tax: inp.total_no_tax(),
+                currency: inp.currency_code(cfg),
+                status: if invoice.is_some() {
+                    "invoiced"
+                } else {
+                    "pending"
+                }
+                .to_string(),
+                descriptions: inp.tx.iter().map(|(descr, ..)| descr.clone()).collect(),
+            })
+            .collect();
+        quotes.sort_by_key(|summary| summary.id);
+        res.extend(quotes);
+    }
+    res
+}
+
+impl DocumentSummary {
+    /// Creation date, unknown for the dates entered as free text that could not be understood
+    pub fn created_day(&self) -> Option<NaiveDate> {
+        match self.created {
+            Date::Day(date) => Some(date),
+            Date::Text(_) => None,
+        }
+    }
+}
+
+/// Filters of the documents listed, all of them must match
+#[derive(clap::Args, Debug, Default)]
+pub struct ListFilters {
+    /// Slug of the recipient
+    #[arg(long)]
+    pub recipient: Option<String>,
+
+    /// Created on this day or after, as YYYY-MM-DD
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+
+    /// Created on this day or before, as YYYY-MM-DD
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+
+    /// Minimum total without taxes, in the currency of the document
+    #[arg(long)]
+    pub min: Option<f64>,
+
+    /// Maximum total without taxes, in the currency of the document
+    #[arg(long)]
+    pub max: Option<f64>,
+
+    /// "issued" for the invoices, "pending" or "invoiced" for the quotations
+    #[arg(long)]
+    pub status: Option<String>,
+
+    /// Text contained in the description of a line, ignoring the case
+    #[arg(long)]
+    pub search: Option<String>,
+}
+
+impl ListFilters {
+    pub fn matches(&self, summary: &DocumentSummary) -> bool {
+        let created = summary.created_day();
+        let search = self.search.as_ref().map(|s| s.to_lowercase());
+        self.recipient.iter().all(|r| *r == summary.recipient)
+            && self
+                .from
+                .iter()
+                .all(|from| created.is_some_and(|c| c >= *from))
+            && self.to.iter().all(|to| created.is_some_and(|c| c <= *to))
+            && self.min.iter().all(|min| summary.total_no_tax >= *min)
+            && self.max.iter().all(|max| summary.total_no_tax <= *max)
+            && self
+                .status
+                .iter()
+                .all(|status| status.eq_ignore_ascii_case(&summary.status))
+            && search.iter().all(|search| {
+                summary
+                    .descriptions
+                    .iter()
+                    .any(|descr| descr.to_lowercase().contains(search))
+            })
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SortKey {
+    Date,
+    Number,
+    Recipient,
+    Amount,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+/// Sorts the documents, the ones with the same key stay in the order they were created
+pub fn sort_summaries(summaries: &mut [DocumentSummary], key: SortKey, reverse: bool) {
+    summaries.sort_by(|a, b| {
+        let ord = match key {
+            // Dates that could not be understood come first
+            SortKey::Date => a.created_day().cmp(&b.created_day()),
+            SortKey::Number => (&a.doctype, a.id).cmp(&(&b.doctype, b.id)),
+            SortKey::Recipient => a.recipient.cmp(&b.recipient),
+            SortKey::Amount => a
+                .total_no_tax
+                .partial_cmp(&b.total_no_tax)
+                .unwrap_or(Ordering::Equal),
+        };
+        if reverse {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+}
+
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn print_csv(summaries: &[DocumentSummary]) {
+    println!("doctype,number,recipient,created,total_no_tax,currency,status,descriptions");
+    for summary in summaries.iter() {
+        let fields = [
+            summary.doctype.clone(),
+            summary.number.clone(),
+            summary.recipient.clone(),
+            summary.created.to_string(),
+            format!("{:.2}", summary.total_no_tax),
+            summary.currency.clone(),
+            summary.status.clone(),
+            summary.descriptions.join("; "),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        println!("{}", fields.join(","));
+    }
+}
+
+/// Writes rows as a table with aligned columns, the columns listed in `right` are
+/// aligned to the right
+pub fn print_table(header: &[&str], rows: &[Vec<String>], right: &[usize]) {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(n, cell)| {
+                let pad = " ".repeat(widths[n] - cell.chars().count());
+                if right.contains(&n) {
+                    format!("{pad}{cell}")
+                } else {
+                    format!("{cell}{pad}")
+                }
+            })
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+pub fn print_summaries(summaries: &[DocumentSummary], lang: &LangDict) -> Result<(), Errcode> {
+    let mut rows = vec![];
+    for summary in summaries.iter() {
+        let amounts = AmountFormat::new(lang, &summary.currency)?;
+        rows.push(vec![
+            summary.doctype.clone(),
+            summary.number.clone(),
+            summary.recipient.clone(),
+            summary.created.localized(lang)?,
+            amounts.format(summary.total_no_tax),
+            summary.status.clone(),
+        ]);
+    }
+    print_table(
+        &["TYPE", "NUMBER", "RECIPIENT", "CREATED", "TOTAL", "STATUS"],
+        &rows,
+        &[4],
+    );
+    Ok(())
+}
+
+#[test]
+fn list_filters() {
+    let summary =
+        |id: usize, recipient: &str, created: Date, total: f64, descr: &str| DocumentSummary {
+            doctype: "invoice".to_string(),
+            id,
+            number: format!("F{id:0>5}"),
+            recipient: recipient.to_string(),
+            profile: None,
+            created,
+            total_no_tax: total,
+            currency: "EUR".to_string(),
+            status: "issued".to_string(),
+            descriptions: vec![descr.to_string()],
+        };
+    let day = |m, d| Date::Day(NaiveDate::from_ymd_opt(2026, m, d).unwrap());
+    let mut list = vec![
+        summary(1, "acme", day(3, 2), 500.0, "Security audit"),
+        summary(
+            2,
+            "initech",
+            Date::Text("last week".to_string()),
+            80.0,
+            "Support",
+        ),
+        summary(3, "acme", day(1, 15), 1200.0, "Audit follow-up"),
+    ];
+    let ids = |filters: ListFilters, list: &[DocumentSummary]| -> Vec<usize> {
+        list.iter()
+            .filter(|s| filters.matches(s))
+            .map(|s| s.id)
+            .collect()
+    };
+
+    let filters = ListFilters {
+        search: Some("AUDIT".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(filters, &list), [1, 3]);
+    let filters = ListFilters {
+        from: NaiveDate::from_ymd_opt(2026, 2, 1),
+        ..Default::default()
+    };
+    assert_eq!(ids(filters, &list), [1]);
+    let filters = ListFilters {
+        recipient: Some("acme".to_string()),
+        max: Some(1000.0),
+        status: Some("Issued".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(filters, &list), [1]);
+
+    sort_summaries(&mut list, SortKey::Date, false);
+    assert_eq!(ids(ListFilters::default(), &list), [2, 3, 1]);
+    sort_summaries(&mut list, SortKey::Amount, true);
+    assert_eq!(ids(ListFilters::default(), &list), [3, 1, 2]);
+    assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+}
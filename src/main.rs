@@ -1,85 +1,961 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use clap::Parser;
+use chrono::{NaiveDate, Utc};
+use clap::{Parser, Subcommand};
 use typst::model::Document;
 
+mod audit;
+mod bank;
 mod codegen;
 mod config;
 mod contact;
+mod currency;
 mod data;
 mod doctype;
 mod errors;
+mod fec;
+mod filename;
 mod fonts;
+mod identifiers;
 mod interface;
 mod lang;
+mod listing;
+mod numbering;
+mod payment_qr;
+mod qr_bill;
+mod rates;
+mod receivables;
+mod reconcile;
+mod report;
+mod storage;
 mod style;
 mod world;
 
-use doctype::DocumentType;
+use audit::{AuditEvent, AuditLog};
+use bank::{import_statement, StatementFormat};
+use contact::Contact;
+use currency::AmountFormat;
+use data::{write_atomic, Datastore};
+use doctype::invoice::Payment;
+use doctype::{DocumentKey, DocumentType, TypstData};
 use errors::Errcode;
+use fec::{fec_entries, fec_fname, fiscal_year, write_fec};
+use listing::{
+    print_csv, print_summaries, print_table, sort_summaries, summaries, ListFilters, OutputFormat,
+    SortKey,
+};
+use rates::RateTable;
+use receivables::aged_balance;
+use reconcile::{propose_matches, record_matches};
+use report::{build_report, Basis, Period, ReportFormat, ReportSettings};
+use storage::{migrate_store, DataLock, Storage, StorageBackend};
 use world::TypstWorld;
 
-use crate::config::import_config;
-use crate::lang::import_lang_profile;
+use crate::config::{import_config, ConfigStore};
+use crate::lang::Languages;
 
 #[derive(Parser, Debug)]
+#[command(version, about = "Generates documents using the typst engine")]
 struct Args {
-    #[arg()]
-    doctype: String,
+    #[command(subcommand)]
+    command: Command,
 
-    #[arg(short, long)]
-    outdir: PathBuf,
-
-    #[arg(short, long)]
+    /// Directory of the configuration and the data, instead of the DOCGEN_ROOT env var
+    #[arg(short, long, global = true)]
     root_dir: Option<PathBuf>,
+
+    /// Issuer profile defined in the config, instead of the default one of the recipient
+    #[arg(short, long, global = true)]
+    profile: Option<String>,
+
+    /// Language of the document, instead of the one of the recipient or the default one
+    #[arg(short, long, global = true)]
+    lang: Option<String>,
+
+    /// Print the data as JSON, for the commands listing or showing data
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a new document, asking for its content
+    New {
+        #[arg()]
+        doctype: String,
+
+        #[arg(short, long)]
+        outdir: PathBuf,
+    },
+
+    /// List the documents issued
+    List {
+        /// Only the documents of this type
+        #[arg()]
+        doctype: Option<String>,
+
+        #[command(flatten)]
+        filters: ListFilters,
+
+        #[arg(long, value_enum, default_value_t = SortKey::Date)]
+        sort: SortKey,
+
+        /// Sort in descending order
+        #[arg(long)]
+        reverse: bool,
+
+        /// Output format, JSON when --json is set
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+
+    /// Show the content of a document saved in the history
+    Show {
+        #[arg()]
+        doctype: String,
+
+        /// Number of the document, or its internal id with --id
+        #[arg()]
+        number: String,
+
+        /// Find the document by its internal id instead of its number
+        #[arg(long)]
+        id: bool,
+    },
+
+    /// Render again a document saved in the history, without changing the data
+    Render {
+        #[arg()]
+        doctype: String,
+
+        /// Number of the document, or its internal id with --id
+        #[arg()]
+        number: String,
+
+        /// Find the document by its internal id instead of its number
+        #[arg(long)]
+        id: bool,
+
+        #[arg(short, long)]
+        outdir: PathBuf,
+
+        /// Use the current configuration instead of the one saved with the document
+        #[arg(long)]
+        current_config: bool,
+    },
+
+    /// Export all the contacts and documents as JSON, or the invoices as a FEC file
+    Export {
+        /// File to write, instead of the standard output, or directory of the FEC file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// Fiscal year of the FEC file, by the year it ends in
+        #[arg(short, long, required_if_eq("format", "fec"))]
+        year: Option<i32>,
+    },
+
+    /// Manage the contacts
+    Contact {
+        #[command(subcommand)]
+        action: ContactCommand,
+    },
+
+    /// Show the configuration used, with the settings of the issuer profile
+    Config,
+
+    /// Revenue and taxes collected of the issued invoices, per period and currency
+    Report {
+        #[arg(long, value_enum, default_value_t = Period::Month)]
+        period: Period,
+
+        /// Count the invoices when created, or the payments when received
+        #[arg(long, value_enum, default_value_t = Basis::Accrual)]
+        basis: Basis,
+
+        /// One line per client in each period
+        #[arg(long)]
+        by_client: bool,
+
+        /// Invoices created, or payments received, on this day or after, as YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+
+        /// Invoices created, or payments received, on this day or before, as YYYY-MM-DD
+        #[arg(long)]
+        to: Option<NaiveDate>,
+
+        /// Output format, JSON when --json is set
+        #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+
+        /// File written with the pdf format
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Record a payment received for an invoice
+    Pay {
+        /// Number of the invoice, or its internal id with --id
+        #[arg()]
+        number: String,
+
+        /// Find the invoice by its internal id instead of its number
+        #[arg(long)]
+        id: bool,
+
+        /// Amount received, in the currency of the invoice
+        #[arg(allow_negative_numbers = true)]
+        amount: f64,
+
+        /// Day the payment was received as YYYY-MM-DD, today if not set
+        #[arg(long)]
+        date: Option<NaiveDate>,
+
+        /// Reference of the transfer, or how it was paid
+        #[arg(long)]
+        reference: Option<String>,
+    },
+
+    /// Amounts owed by each client, by how late the payment is
+    Receivables {
+        /// Balance on this day as YYYY-MM-DD, today if not set
+        #[arg(long)]
+        as_of: Option<NaiveDate>,
+
+        /// Output format, JSON when --json is set
+        #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+
+        /// File written with the pdf format
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Match the credits of a bank statement with the open invoices, and record them as payments
+    Reconcile {
+        /// Bank statement, as CAMT.053 XML, OFX or CSV
+        #[arg()]
+        file: PathBuf,
+
+        /// Format of the statement, detected from its content if not set
+        #[arg(long, value_enum)]
+        format: Option<StatementFormat>,
+
+        /// Record the proposed matches without reviewing them
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Copy all the data from the configured storage backend to another one
+    MigrateStore {
+        #[arg(long)]
+        to: StorageBackend,
+    },
+
+    /// Check that the audit log was not tampered with and matches the saved data
+    VerifyAudit,
+
+    /// Start the audit log of data saved before the log existed
+    InitAudit,
+
+    /// Add the exchange rates of an ECB reference rates file (CSV or XML)
+    ImportRates {
+        #[arg()]
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ContactCommand {
+    /// List all the contacts
+    List,
+
+    /// Show the details of a contact
+    Show {
+        #[arg()]
+        slug: String,
+    },
+
+    /// Add a contact, asking for its details
+    Add {
+        #[arg()]
+        slug: Option<String>,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+enum ExportFormat {
+    Json,
+    /// Fichier des écritures comptables, for the French tax administration
+    Fec,
 }
 
 impl Args {
-    fn get_root(&self) -> PathBuf {
+    fn get_root(&self) -> Result<PathBuf, Errcode> {
         if let Some(ref root) = self.root_dir {
-            root.clone()
+            Ok(root.clone())
         } else if let Ok(root) = std::env::var("DOCGEN_ROOT") {
-            root.into()
+            Ok(root.into())
         } else {
-            panic!("Root directory must be set using --root-dir or the DOCGEN_ROOT env var");
+            Err(Errcode::InvalidConfig(
+                "root",
+                "Root directory must be set using --root-dir or the DOCGEN_ROOT env var"
+                    .to_string(),
+            ))
         }
     }
 }
 
-fn export(outf: &PathBuf, doc: &Document) -> Result<(), Errcode> {
+fn export(outf: &Path, doc: &Document) -> Result<(), Errcode> {
     let res = typst_pdf::pdf(doc, None, None);
-    std::fs::write(outf, res)?;
+    write_atomic(outf, &res)
+}
+
+fn write_pdf(world: &mut TypstWorld, source: TypstData, outdir: &Path) -> Result<PathBuf, Errcode> {
+    let outfile = source.collision.resolve(&outdir.join(&source.fname))?;
+    if let Some(parent) = outfile.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    println!("[*] Compiling the source code");
+    let doc = world.compile(source)?;
+
+    println!("[*] Rendering the PDF file");
+    export(&outfile, &doc)?;
+    Ok(outfile)
+}
+
+/// Configuration of the issuer profile asked for, if any
+fn load_config(root: &Path, profile: Option<&str>) -> Result<ConfigStore, Errcode> {
+    let config = import_config(&root.join("config.toml"))?;
+    match profile {
+        Some(profile) => config.for_profile(profile),
+        None => Ok(config),
+    }
+}
+
+/// Loads all the data to change it, the lock must be kept until the data is saved
+fn load_data(
+    root: &Path,
+    config: &ConfigStore,
+) -> Result<(DataLock, Box<dyn Storage>, Datastore), Errcode> {
+    let datadir = root.join("data");
+    let lock = DataLock::acquire(&datadir)?;
+    let store = StorageBackend::from_config(config)?.open(&datadir)?;
+    let mut data = Datastore::import(store.as_ref())?;
+    data.rates = RateTable::load(&datadir)?;
+    Ok((lock, store, data))
+}
+
+/// Loads all the data to read it, without waiting for the commands changing it
+fn read_data(root: &Path, config: &ConfigStore) -> Result<Datastore, Errcode> {
+    let datadir = root.join("data");
+    let store = StorageBackend::from_config(config)?.open_read_only(&datadir)?;
+    let mut data = Datastore::import(store.as_ref())?;
+    data.rates = RateTable::load(&datadir)?;
+    Ok(data)
+}
+
+/// Saves the data and logs its changes, the audit entries are written aside first so
+/// that no change can be saved without them
+fn save_data(
+    store: &mut dyn Storage,
+    data: &Datastore,
+    audit: &mut AuditLog,
+) -> Result<(), Errcode> {
+    audit.prepare(&data.audit)?;
+    if let Err(e) = data.export(store) {
+        audit.abort()?;
+        return Err(e);
+    }
+    audit.commit()
+}
+
+fn print_json<T: serde::Serialize>(data: &T) -> Result<(), Errcode> {
+    println!("{}", serde_json::to_string_pretty(data)?);
     Ok(())
 }
 
-fn main() {
-    println!("[*] Getting the configuration");
-    let args = Args::parse();
-    let root = args.get_root();
-    if !root.exists() {
-        std::fs::create_dir_all(&root).expect("Unable to create root directory");
+fn generate_document(
+    root: &Path,
+    doctype: &String,
+    outdir: &Path,
+    profile: Option<&str>,
+    lang: Option<&str>,
+) -> Result<(), Errcode> {
+    let doctype: DocumentType = doctype.try_into()?;
+    let langs = Languages::new(root, lang)?;
+    let config = import_config(&root.join("config.toml"))?;
+
+    println!("[*] Initializing Typst compilation context");
+    let mut world = TypstWorld::new(root, &doctype.to_string(), &config)?;
+
+    println!("[*] Generating the source code");
+    let (_lock, mut store, mut data) = load_data(root, &config)?;
+    let mut audit = AuditLog::open(&root.join("data"), &data)?;
+    let source = doctype.generate_typst(&config, &langs, &mut data, profile)?;
+    let outfile = write_pdf(&mut world, source, outdir)?;
+
+    println!("[*] Saving the data");
+    if let Err(e) = save_data(store.as_mut(), &data, &mut audit) {
+        let _ = std::fs::remove_file(&outfile);
+        println!("[!] Unable to save the data, the generated document was removed");
+        return Err(e);
     }
-    let doctype: DocumentType = (&args.doctype).try_into().unwrap();
-    let lang = import_lang_profile(&root.join("lang.toml")).expect("Unable to load lang file");
-    let config = import_config(&root.join("config.toml")).expect("Unable to load config");
+    println!("[*] Document written to {outfile:?}");
+    Ok(())
+}
+
+fn render_document(
+    root: &Path,
+    doctype: &String,
+    key: &DocumentKey,
+    outdir: &Path,
+    profile: Option<&str>,
+    lang: Option<&str>,
+    use_current_cfg: bool,
+) -> Result<(), Errcode> {
+    let doctype: DocumentType = doctype.try_into()?;
+    let langs = Languages::new(root, lang)?;
+    let config = import_config(&root.join("config.toml"))?;
 
     println!("[*] Initializing Typst compilation context");
-    let mut world = TypstWorld::new(&root, doctype).expect("Unable to create Typst context");
+    let mut world = TypstWorld::new(root, &doctype.to_string(), &config)?;
 
     println!("[*] Generating the source code");
-    let source = doctype
-        .generate_typst(&config, &lang, &root.join("data"))
-        .expect("Unable to generate typst code");
-    if !args.outdir.exists() {
-        std::fs::create_dir_all(&args.outdir).expect("Unable to create output directory");
+    let data = read_data(root, &config)?;
+    let source = doctype.render_typst(&config, &langs, &data, key, profile, use_current_cfg)?;
+    let outfile = write_pdf(&mut world, source, outdir)?;
+    println!("[*] Document written to {outfile:?}");
+    Ok(())
+}
+
+fn list_documents(
+    args: &Args,
+    root: &Path,
+    doctype: &Option<String>,
+    filters: &ListFilters,
+    sort: SortKey,
+    reverse: bool,
+    format: OutputFormat,
+) -> Result<(), Errcode> {
+    let doctype: Option<DocumentType> = doctype.as_ref().map(|d| d.try_into()).transpose()?;
+    let config = load_config(root, args.profile.as_deref())?;
+    let data = read_data(root, &config)?;
+    let mut list = summaries(&config, &data, doctype);
+    list.retain(|summary| {
+        filters.matches(summary)
+            && args
+                .profile
+                .iter()
+                .all(|p| summary.profile.as_ref() == Some(p))
+    });
+    sort_summaries(&mut list, sort, reverse);
+    let format = if args.json {
+        OutputFormat::Json
+    } else {
+        format
+    };
+    match format {
+        OutputFormat::Json => print_json(&list),
+        OutputFormat::Csv => {
+            print_csv(&list);
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let (_, lang) = Languages::new(root, args.lang.as_deref())?.select(&config, &None)?;
+            print_summaries(&list, &lang)
+        }
     }
-    let outfile = args.outdir.join(&source.fname);
+}
 
-    println!("[*] Compiling the source code");
-    let doc = world
-        .compile(source)
-        .expect("Unable to compile generated typst code");
+fn show_document(
+    args: &Args,
+    root: &Path,
+    doctype: &String,
+    key: &DocumentKey,
+) -> Result<(), Errcode> {
+    let doctype: DocumentType = doctype.try_into()?;
+    let config = import_config(&root.join("config.toml"))?;
+    let data = read_data(root, &config)?;
+    let profile = args.profile.as_deref();
+    let not_found = || Errcode::DocumentNotFound(key.to_string());
+    let (record, tx, id) = match doctype {
+        DocumentType::Invoice => {
+            let inp = data.invoices.find(&config, key, profile)?;
+            (serde_json::to_value(inp)?, inp.tx.clone(), inp.id)
+        }
+        DocumentType::Quotation => {
+            let (inp, _) = data.quotations.find(&config, key, profile)?;
+            (serde_json::to_value(inp)?, inp.tx.clone(), inp.id)
+        }
+        DocumentType::Statement => return Err(not_found()),
+    };
+    if args.json {
+        return print_json(&record);
+    }
 
-    println!("[*] Rendering the PDF file");
-    export(&outfile, &doc).expect("Unable to export to file");
+    let summary = summaries(&config, &data, Some(doctype))
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(not_found)?;
+    let (_, lang) = Languages::new(root, args.lang.as_deref())?.select(&config, &None)?;
+    let amounts = AmountFormat::new(&lang, &summary.currency)?;
+    println!("{} {}", summary.doctype, summary.number);
+    println!("Recipient: {}", summary.recipient);
+    println!("Created: {}", summary.created.localized(&lang)?);
+    if let Some(ref profile) = summary.profile {
+        println!("Profile: {profile}");
+    }
+    println!("Status: {}\n", summary.status);
+    let rows: Vec<Vec<String>> = tx
+        .iter()
+        .map(|(descr, units, ppu)| {
+            vec![
+                descr.clone(),
+                amounts.locale.quantity(*units),
+                amounts.format(*ppu),
+                amounts.format(units * ppu),
+            ]
+        })
+        .collect();
+    print_table(
+        &["DESCRIPTION", "UNITS", "PRICE", "TOTAL"],
+        &rows,
+        &[1, 2, 3],
+    );
+    println!("\nTotal: {}", amounts.format(summary.total_no_tax));
+    Ok(())
+}
+
+fn export_data(root: &Path, output: &Option<PathBuf>) -> Result<(), Errcode> {
+    let config = import_config(&root.join("config.toml"))?;
+    let data = read_data(root, &config)?;
+    let content = serde_json::to_string_pretty(&data.as_json()?)?;
+    match output {
+        Some(fname) => write_atomic(fname, content.as_bytes()),
+        None => {
+            println!("{content}");
+            Ok(())
+        }
+    }
+}
+
+fn export_fec(
+    args: &Args,
+    root: &Path,
+    output: &Option<PathBuf>,
+    year: i32,
+) -> Result<(), Errcode> {
+    let config = load_config(root, args.profile.as_deref())?;
+    let data = read_data(root, &config)?;
+    let period = fiscal_year(&config, year)?;
+    let (rows, undated) = fec_entries(&config, &data, &args.profile, period)?;
+    if undated > 0 {
+        eprintln!(
+            "[!] {undated} invoices with a creation date that could not be understood are not exported"
+        );
+    }
+    let outdir = output.clone().unwrap_or_default();
+    let fname = outdir.join(fec_fname(&config, &period.1)?);
+    write_atomic(&fname, write_fec(&config, &rows)?.as_bytes())?;
+    println!("[*] {} entry lines written to {fname:?}", rows.len());
+    Ok(())
+}
+
+fn manage_contacts(args: &Args, root: &Path, action: &ContactCommand) -> Result<(), Errcode> {
+    let config = import_config(&root.join("config.toml"))?;
+    let (_lock, mut store, mut data) = load_data(root, &config)?;
+    match action {
+        ContactCommand::List => {
+            let mut contacts: Vec<&Contact> = data.contacts.iter().map(|(_, c)| c).collect();
+            contacts.sort_by(|a, b| a.slug.cmp(&b.slug));
+            if args.json {
+                return print_json(&contacts);
+            }
+            let rows: Vec<Vec<String>> = contacts
+                .iter()
+                .map(|c| {
+                    vec![
+                        c.slug.clone(),
+                        c.name.clone(),
+                        c.invoices.len().to_string(),
+                        c.quotations.len().to_string(),
+                    ]
+                })
+                .collect();
+            print_table(&["SLUG", "NAME", "INVOICES", "QUOTATIONS"], &rows, &[2, 3]);
+        }
+        ContactCommand::Show { slug } => {
+            if !data.contacts.contains(slug) {
+                return Err(Errcode::ContactNotFound(slug.clone()));
+            }
+            let contact = data.contacts.get(slug);
+            if args.json {
+                return print_json(contact);
+            }
+            println!("{} ({})", contact.name, contact.slug);
+            println!("{}", contact.address);
+            for (name, val) in [
+                ("Profile", &contact.profile),
+                ("Currency", &contact.currency),
+                ("Language", &contact.lang),
+                ("VAT number", &contact.vat_number),
+            ] {
+                if let Some(val) = val {
+                    println!("{name}: {val}");
+                }
+            }
+        }
+        ContactCommand::Add { slug } => {
+            let slug = slug.clone().unwrap_or_else(Contact::ask_slug);
+            if data.contacts.contains(&slug) {
+                return Err(Errcode::InvalidConfig(
+                    "contact",
+                    format!("Contact {slug} already exists"),
+                ));
+            }
+            let mut audit = AuditLog::open(&root.join("data"), &data)?;
+            data.get_or_add_contact(&slug);
+            save_data(store.as_mut(), &data, &mut audit)?;
+            println!("[*] Contact {slug} added");
+        }
+    }
+    Ok(())
+}
+
+fn show_config(args: &Args, root: &Path) -> Result<(), Errcode> {
+    let config = load_config(root, args.profile.as_deref())?;
+    if args.json {
+        print_json(&config)
+    } else {
+        print!("{}", toml::to_string(&config)?);
+        Ok(())
+    }
+}
+
+fn report(
+    args: &Args,
+    root: &Path,
+    settings: &ReportSettings,
+    format: ReportFormat,
+    output: &Option<PathBuf>,
+) -> Result<(), Errcode> {
+    let config = load_config(root, args.profile.as_deref())?;
+    let data = read_data(root, &config)?;
+    let report = build_report(&config, &data, settings);
+    if report.undated > 0 {
+        eprintln!(
+            "[!] {} invoices with a creation date that could not be understood are not counted",
+            report.undated
+        );
+    }
+    let (_, lang) = Languages::new(root, args.lang.as_deref())?.select(&config, &None)?;
+    let format = if args.json {
+        ReportFormat::Json
+    } else {
+        format
+    };
+    match format {
+        ReportFormat::Json => print_json(&report),
+        ReportFormat::Csv => report.print_csv(),
+        ReportFormat::Table => report.print_table(&lang),
+        ReportFormat::Pdf => {
+            let output = output.as_ref().ok_or_else(|| {
+                Errcode::InvalidConfig("report", "Set the PDF file with --output".to_string())
+            })?;
+            let code = report.typst_code(&config, &lang, settings)?;
+            let mut world = TypstWorld::new(root, "report", &config)?;
+            let source = TypstData::new(output.clone(), code, &config)?;
+            let outfile = write_pdf(&mut world, source, Path::new(""))?;
+            println!("[*] Report written to {outfile:?}");
+            Ok(())
+        }
+    }
+}
+
+fn record_payment(
+    args: &Args,
+    root: &Path,
+    key: &DocumentKey,
+    payment: Payment,
+) -> Result<(), Errcode> {
+    let config = import_config(&root.join("config.toml"))?;
+    let (_lock, mut store, mut data) = load_data(root, &config)?;
+    let mut audit = AuditLog::open(&root.join("data"), &data)?;
+    let (date, amount) = (payment.date, payment.amount);
+    let (invoice, balance) =
+        data.invoices
+            .record_payment(&config, key, args.profile.as_deref(), payment)?;
+    data.audit.push(AuditEvent::PaymentRecorded {
+        invoice,
+        amount,
+        date,
+    });
+    save_data(store.as_mut(), &data, &mut audit)?;
+    let (_, lang) = Languages::new(root, args.lang.as_deref())?.select(&config, &None)?;
+    let currency = data
+        .invoices
+        .history
+        .iter()
+        .find(|inp| inp.id == invoice)
+        .unwrap()
+        .currency_code(&config);
+    let amounts = AmountFormat::new(&lang, &currency)?;
+    if balance < -0.005 {
+        println!(
+            "[!] Invoice {key} was paid {} more than its total",
+            amounts.format(-balance)
+        );
+    } else if balance < 0.005 {
+        println!("[*] Invoice {key} is fully paid");
+    } else {
+        println!(
+            "[*] Payment recorded, {} left to pay on invoice {key}",
+            amounts.format(balance)
+        );
+    }
+    Ok(())
+}
+
+fn receivables(
+    args: &Args,
+    root: &Path,
+    as_of: Option<NaiveDate>,
+    format: ReportFormat,
+    output: &Option<PathBuf>,
+) -> Result<(), Errcode> {
+    let config = load_config(root, args.profile.as_deref())?;
+    let data = read_data(root, &config)?;
+    let as_of = as_of.unwrap_or_else(|| Utc::now().date_naive());
+    let aged = aged_balance(&config, &data, as_of, &args.profile);
+    if aged.undated > 0 {
+        eprintln!(
+            "[!] {} invoices with a creation date that could not be understood are not counted",
+            aged.undated
+        );
+    }
+    let (_, lang) = Languages::new(root, args.lang.as_deref())?.select(&config, &None)?;
+    let format = if args.json {
+        ReportFormat::Json
+    } else {
+        format
+    };
+    match format {
+        ReportFormat::Json => print_json(&aged),
+        ReportFormat::Csv => aged.print_csv(),
+        ReportFormat::Table => aged.print_table(&lang),
+        ReportFormat::Pdf => {
+            let output = output.as_ref().ok_or_else(|| {
+                Errcode::InvalidConfig("report", "Set the PDF file with --output".to_string())
+            })?;
+            let code = aged.typst_code(&config, &lang)?;
+            let mut world = TypstWorld::new(root, "report", &config)?;
+            let source = TypstData::new(output.clone(), code, &config)?;
+            let outfile = write_pdf(&mut world, source, Path::new(""))?;
+            println!("[*] Report written to {outfile:?}");
+            Ok(())
+        }
+    }
+}
+
+fn reconcile(
+    args: &Args,
+    root: &Path,
+    file: &Path,
+    format: Option<StatementFormat>,
+    yes: bool,
+) -> Result<(), Errcode> {
+    let config = load_config(root, args.profile.as_deref())?;
+    let (_lock, mut store, mut data) = load_data(root, &config)?;
+    let mut audit = AuditLog::open(&root.join("data"), &data)?;
+    let content = std::fs::read_to_string(file)?;
+    let credits = import_statement(&content, format)?;
+    let (mut proposals, nb_recorded) = propose_matches(&config, &data, credits, &args.profile);
+    if nb_recorded > 0 {
+        println!("[*] {nb_recorded} credits of this statement were already recorded");
+    }
+    if proposals.is_empty() {
+        println!("[*] No new credit to reconcile");
+        return Ok(());
+    }
+    let (_, lang) = Languages::new(root, args.lang.as_deref())?.select(&config, &None)?;
+    if yes {
+        for proposal in proposals.iter() {
+            println!("{}", proposal.display(&lang)?);
+        }
+    } else if !interface::review_matches(&mut proposals, &lang)? {
+        println!("[*] Reconciliation cancelled, nothing recorded");
+        return Ok(());
+    }
+    let (nb, overpaid) = record_matches(&mut data, &proposals);
+    save_data(store.as_mut(), &data, &mut audit)?;
+    println!(
+        "[*] {nb} payments recorded, {} credits left unmatched",
+        proposals.len() - nb
+    );
+    for (invoice, excess) in overpaid {
+        println!(
+            "[!] Invoice {} was paid {} more than its total",
+            invoice.number,
+            AmountFormat::new(&lang, &invoice.currency)?.format(excess)
+        );
+    }
+    Ok(())
+}
+
+fn verify_audit(root: &Path) -> Result<(), Errcode> {
+    let config = import_config(&root.join("config.toml"))?;
+    let data = read_data(root, &config)?;
+    let problems = AuditLog::open_read_only(&root.join("data")).verify(&data)?;
+    if problems.is_empty() {
+        println!("[*] Audit log verified, no problem found");
+        Ok(())
+    } else {
+        for problem in problems.iter() {
+            println!("[!] {problem}");
+        }
+        Err(Errcode::AuditMismatch(problems.len()))
+    }
+}
+
+fn init_audit(root: &Path) -> Result<(), Errcode> {
+    let config = import_config(&root.join("config.toml"))?;
+    let (_lock, _, data) = load_data(root, &config)?;
+    AuditLog::init(&root.join("data"), &data)?;
+    println!("[*] Audit log started, the documents saved before are not audited");
+    Ok(())
+}
+
+fn import_rates(root: &Path, file: &Path) -> Result<(), Errcode> {
+    let datadir = root.join("data");
+    let _lock = DataLock::acquire(&datadir)?;
+    let mut rates = RateTable::load(&datadir)?;
+    let content = std::fs::read_to_string(file)?;
+    let nb_days = rates.import_ecb(&content)?;
+    rates.save(&datadir)?;
+    println!("[*] Exchange rates of {nb_days} days imported");
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<(), Errcode> {
+    let root = args.get_root()?;
+    if !root.exists() {
+        std::fs::create_dir_all(&root)?;
+    }
+    let profile = args.profile.as_deref();
+    let lang = args.lang.as_deref();
+
+    match &args.command {
+        Command::New { doctype, outdir } => {
+            generate_document(&root, doctype, outdir, profile, lang)
+        }
+        Command::List {
+            doctype,
+            filters,
+            sort,
+            reverse,
+            format,
+        } => list_documents(args, &root, doctype, filters, *sort, *reverse, *format),
+        Command::Show {
+            doctype,
+            number,
+            id,
+        } => show_document(args, &root, doctype, &DocumentKey::from_arg(number, *id)?),
+        Command::Render {
+            doctype,
+            number,
+            id,
+            outdir,
+            current_config,
+        } => render_document(
+            &root,
+            doctype,
+            &DocumentKey::from_arg(number, *id)?,
+            outdir,
+            profile,
+            lang,
+            *current_config,
+        ),
+        Command::Export {
+            output,
+            format: ExportFormat::Json,
+            ..
+        } => export_data(&root, output),
+        Command::Export {
+            output,
+            format: ExportFormat::Fec,
+            year,
+        } => export_fec(args, &root, output, year.unwrap()),
+        Command::Contact { action } => manage_contacts(args, &root, action),
+        Command::Config => show_config(args, &root),
+        Command::Report {
+            period,
+            basis,
+            by_client,
+            from,
+            to,
+            format,
+            output,
+        } => {
+            let settings = ReportSettings {
+                period: *period,
+                basis: *basis,
+                by_client: *by_client,
+                from: *from,
+                to: *to,
+                profile: args.profile.clone(),
+            };
+            report(args, &root, &settings, *format, output)
+        }
+        Command::Pay {
+            number,
+            id,
+            amount,
+            date,
+            reference,
+        } => {
+            let payment = Payment {
+                date: date.unwrap_or_else(|| Utc::now().date_naive()),
+                amount: *amount,
+                reference: reference.clone(),
+                bank_id: None,
+            };
+            record_payment(args, &root, &DocumentKey::from_arg(number, *id)?, payment)
+        }
+        Command::Receivables {
+            as_of,
+            format,
+            output,
+        } => receivables(args, &root, *as_of, *format, output),
+        Command::Reconcile { file, format, yes } => reconcile(args, &root, file, *format, *yes),
+        Command::MigrateStore { to } => {
+            let config = import_config(&root.join("config.toml"))?;
+            let from = StorageBackend::from_config(&config)?;
+            let datadir = root.join("data");
+            let _lock = DataLock::acquire(&datadir)?;
+            migrate_store(&datadir, from, *to)?;
+            println!("[*] Data copied, set `backend = \"{to}\"` in the [storage] config to use it");
+            Ok(())
+        }
+        Command::VerifyAudit => verify_audit(&root),
+        Command::InitAudit => init_audit(&root),
+        Command::ImportRates { file } => import_rates(&root, file),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("[!] {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Utc};
+
+use crate::config::ConfigStore;
+use crate::errors::Errcode;
+
+/// Next sequence number of each numbering scheme
+pub type Counters = HashMap<String, usize>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ResetPeriod {
+    Never,
+    Yearly,
+    Monthly,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Text(String),
+    Prefix,
+    Year,
+    ShortYear,
+    Month,
+    Sequence(usize),
+}
+
+/// How document numbers are built, from a pattern like `{prefix}-{YYYY}-{seq:4}`
+pub struct NumberingScheme {
+    tokens: Vec<Token>,
+    prefix: String,
+    reset: ResetPeriod,
+}
+
+impl NumberingScheme {
+    pub fn from_config(cfg: &ConfigStore, doctype: &str) -> Result<NumberingScheme, Errcode> {
+        let reset = match cfg.get_str(doctype, "numbering_reset") {
+            "never" => ResetPeriod::Never,
+            "yearly" => ResetPeriod::Yearly,
+            "monthly" => ResetPeriod::Monthly,
+            r => {
+                return Err(Errcode::InvalidConfig(
+                    "numbering",
+                    format!("Unknown reset period {r:?} for {doctype}"),
+                ))
+            }
+        };
+        NumberingScheme::new(
+            cfg.get_str(doctype, "numbering"),
+            cfg.get_str(doctype, "id_prefix"),
+            reset,
+        )
+    }
+
+    pub fn new(
+        pattern: &str,
+        prefix: &str,
+        reset: ResetPeriod,
+    ) -> Result<NumberingScheme, Errcode> {
+        let invalid = |msg: String| Errcode::InvalidConfig("numbering", msg);
+        let mut tokens = vec![];
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                tokens.push(Token::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid(format!("Unclosed token in {pattern:?}")))?;
+            let token = match &rest[start + 1..start + end] {
+                "prefix" => Token::Prefix,
+                "YYYY" => Token::Year,
+                "YY" => Token::ShortYear,
+                "MM" => Token::Month,
+                "seq" => Token::Sequence(0),
+                t => match t.strip_prefix("seq:").map(|pad| pad.parse::<usize>()) {
+                    Some(Ok(pad)) => Token::Sequence(pad),
+                    _ => return Err(invalid(format!("Unknown token {t:?} in {pattern:?}"))),
+                },
+            };
+            tokens.push(token);
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            tokens.push(Token::Text(rest.to_string()));
+        }
+
+        let nb_seq = tokens
+            .iter()
+            .filter(|t| matches!(t, Token::Sequence(_)))
+            .count();
+        if nb_seq != 1 {
+            return Err(invalid(format!(
+                "Pattern {pattern:?} must contain the {{seq}} token exactly once"
+            )));
+        }
+        // Without the period in the number, a new sequence would repeat the same numbers
+        let has = |wanted: &[Token]| tokens.iter().any(|t| wanted.contains(t));
+        let has_period = match reset {
+            ResetPeriod::Never => true,
+            ResetPeriod::Yearly => has(&[Token::Year, Token::ShortYear]),
+            ResetPeriod::Monthly => has(&[Token::Year, Token::ShortYear]) && has(&[Token::Month]),
+        };
+        if !has_period {
+            return Err(invalid(format!(
+                "Pattern {pattern:?} must contain the year{} to reset the sequence {}",
+                if reset == ResetPeriod::Monthly {
+                    " and {MM}"
+                } else {
+                    ""
+                },
+                if reset == ResetPeriod::Monthly {
+                    "monthly"
+                } else {
+                    "yearly"
+                },
+            )));
+        }
+        Ok(NumberingScheme {
+            tokens,
+            prefix: prefix.to_string(),
+            reset,
+        })
+    }
+
+    fn expand(&self, date: &DateTime<Utc>, seq: Option<usize>) -> String {
+        let mut res = String::new();
+        for token in self.tokens.iter() {
+            match token {
+                Token::Text(t) => res += t,
+                Token::Prefix => res += &self.prefix,
+                Token::Year => res += &format!("{:0>4}", date.year()),
+                Token::ShortYear => res += &format!("{:0>2}", date.year() % 100),
+                Token::Month => res += &format!("{:0>2}", date.month()),
+                Token::Sequence(pad) => match seq {
+                    Some(seq) => res += &format!("{seq:0>pad$}"),
+                    None => res += "{seq}",
+                },
+            }
+        }
+        res
+    }
+
+    /// Each pattern and reset period has its own counter, the date tokens of a sequence
+    /// that never resets are kept as is so that it goes on across years
+    fn counter_key(&self, date: &DateTime<Utc>) -> String {
+        let period = match self.reset {
+            ResetPeriod::Never => return self.undated_key(),
+            ResetPeriod::Yearly => date.format("@%Y").to_string(),
+            ResetPeriod::Monthly => date.format("@%Y-%m").to_string(),
+        };
+        self.expand(date, None) + &period
+    }
+
+    fn undated_key(&self) -> String {
+        let mut res = String::new();
+        for token in self.tokens.iter() {
+            match token {
+                Token::Text(t) => res += t,
+                Token::Prefix => res += &self.prefix,
+                Token::Year => res += "{YYYY}",
+                Token::ShortYear => res += "{YY}",
+                Token::Month => res += "{MM}",
+                Token::Sequence(_) => res += "{seq}",
+            }
+        }
+        res
+    }
+
+    /// Whether the key is the one used by older versions for this pattern, which had
+    /// the date expanded even when the sequence never resets
+    fn is_dated_key(&self, key: &str) -> bool {
+        let mut rest = key;
+        for token in self.tokens.iter() {
+            let len = match token {
+                Token::Text(t) => t.len(),
+                Token::Prefix => self.prefix.len(),
+                Token::Sequence(_) => "{seq}".len(),
+                Token::Year => 4,
+                Token::ShortYear | Token::Month => 2,
+            };
+            let Some(part) = rest.get(..len) else {
+                return false;
+            };
+            let valid = match token {
+                Token::Text(t) => part == t,
+                Token::Prefix => part == self.prefix,
+                Token::Sequence(_) => part == "{seq}",
+                _ => part.chars().all(|c| c.is_ascii_digit()),
+            };
+            if !valid {
+                return false;
+            }
+            rest = &rest[len..];
+        }
+        rest.is_empty()
+    }
+
+    /// Takes the next number of the sequence, `legacy_next` is the id that would
+    /// have been used before numbering schemes existed, if any
+    pub fn allocate(
+        &self,
+        counters: &mut Counters,
+        legacy_next: Option<usize>,
+        date: &DateTime<Utc>,
+    ) -> String {
+        // The first sequence that never resets continues the numbers already issued
+        let mut first = match legacy_next {
+            Some(next) if counters.is_empty() && (self.reset == ResetPeriod::Never) => next,
+            _ => 1,
+        };
+        let key = self.counter_key(date);
+        if (self.reset == ResetPeriod::Never) && !counters.contains_key(&key) {
+            let dated = counters.iter().filter(|(k, _)| self.is_dated_key(k));
+            first = dated.map(|(_, next)| *next).fold(first, usize::max);
+        }
+        let counter = counters.entry(key).or_insert(first);
+        let number = self.expand(date, Some(*counter));
+        *counter += 1;
+        number
+    }
+}
+
+#[test]
+fn numbering_patterns() {
+    use chrono::TimeZone;
+    let date = Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+    let next_year = Utc.with_ymd_and_hms(2027, 1, 2, 12, 0, 0).unwrap();
+
+    let legacy = NumberingScheme::new("{prefix}{seq:5}", "F", ResetPeriod::Never).unwrap();
+    let mut counters = HashMap::new();
+    assert_eq!(legacy.allocate(&mut counters, Some(42), &date), "F00042");
+    assert_eq!(
+        legacy.allocate(&mut counters, Some(43), &next_year),
+        "F00043"
+    );
+
+    let yearly = NumberingScheme::new("F-{YYYY}-{seq:4}", "", ResetPeriod::Yearly).unwrap();
+    assert_eq!(
+        yearly.allocate(&mut counters, Some(44), &date),
+        "F-2026-0001"
+    );
+    assert_eq!(
+        yearly.allocate(&mut counters, Some(45), &date),
+        "F-2026-0002"
+    );
+    assert_eq!(
+        yearly.allocate(&mut counters, Some(46), &next_year),
+        "F-2027-0001"
+    );
+
+    let monthly =
+        NumberingScheme::new("{prefix}{YY}{MM}/{seq}", "A", ResetPeriod::Monthly).unwrap();
+    assert_eq!(monthly.allocate(&mut counters, Some(47), &date), "A2610/1");
+    assert_eq!(legacy.allocate(&mut counters, Some(48), &date), "F00044");
+
+    // Other profiles start their own sequences from 1
+    let mut profile_counters = HashMap::new();
+    assert_eq!(
+        legacy.allocate(&mut profile_counters, None, &date),
+        "F00001"
+    );
+
+    assert!(NumberingScheme::new("{prefix}", "F", ResetPeriod::Never).is_err());
+    assert!(NumberingScheme::new("{seq}{seq}", "F", ResetPeriod::Never).is_err());
+    assert!(NumberingScheme::new("{year}{seq}", "F", ResetPeriod::Never).is_err());
+    assert!(NumberingScheme::new("{seq", "F", ResetPeriod::Never).is_err());
+
+    // The period must appear in the numbers when the sequence resets
+    assert!(NumberingScheme::new("{prefix}{seq}", "F", ResetPeriod::Yearly).is_err());
+    assert!(NumberingScheme::new("{prefix}{MM}{seq}", "F", ResetPeriod::Yearly).is_err());
+    assert!(NumberingScheme::new("{prefix}{YY}{seq}", "F", ResetPeriod::Monthly).is_err());
+    assert!(NumberingScheme::new("{prefix}{MM}{seq}", "F", ResetPeriod::Monthly).is_err());
+    assert!(NumberingScheme::new("{YYYY}{MM}-{seq}", "F", ResetPeriod::Monthly).is_ok());
+
+    // A sequence that never resets goes on across years, even with the year in it
+    let dated = NumberingScheme::new("D{YY}-{seq}", "", ResetPeriod::Never).unwrap();
+    let mut counters = HashMap::new();
+    assert_eq!(dated.allocate(&mut counters, None, &date), "D26-1");
+    assert_eq!(dated.allocate(&mut counters, None, &next_year), "D27-2");
+
+    // and continues the counters of the older versions, which had the year in their key
+    let mut counters = HashMap::from([("D26-{seq}".to_string(), 8)]);
+    assert_eq!(dated.allocate(&mut counters, None, &next_year), "D27-8");
+}
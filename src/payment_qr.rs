@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+
+use crate::doctype::snapshot::BankDetails;
+use crate::errors::Errcode;
+
+/// Path of the QR code in the assets of the document
+pub const EPC_QR_ASSET: &str = "generated/epc_qr.svg";
+
+/// Longest amount allowed by the EPC guidelines
+const EPC_MAX_AMOUNT: f64 = 999_999_999.99;
+
+fn truncate(text: &str, max: usize) -> String {
+    text.chars().take(max).collect()
+}
+
+/// Content of the EPC069-12 QR code of a SEPA credit transfer, none if the amount cannot
+/// be paid this way
+pub fn epc_payload(
+    beneficiary: &str,
+    bank: &BankDetails,
+    amount: f64,
+    currency: &str,
+    reference: &str,
+) -> Option<String> {
+    if (currency != "EUR") || !(0.01..=EPC_MAX_AMOUNT).contains(&amount) {
+        return None;
+    }
+    let compact = |text: &str| text.split_whitespace().collect::<String>().to_uppercase();
+    let lines = [
+        "BCD".to_string(),
+        "002".to_string(),
+        // UTF-8
+        "1".to_string(),
+        "SCT".to_string(),
+        compact(&bank.bic),
+        truncate(beneficiary.trim(), 70),
+        compact(&bank.iban),
+        format!("EUR{amount:.2}"),
+        // Purpose and structured reference
+        "".to_string(),
+        "".to_string(),
+        truncate(reference.trim(), 140),
+    ];
+    Some(lines.join("\n"))
+}
+
+/// SVG image of the QR code, with the error correction level required by the EPC
+pub fn qr_code_svg(payload: &str) -> Result<(PathBuf, Vec<u8>), Errcode> {
+    let code = QrCode::with_error_correction_level(payload.as_bytes(), EcLevel::M)?;
+    let image = code
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .quiet_zone(true)
+        .build();
+    Ok((PathBuf::from(EPC_QR_ASSET), image.into_bytes()))
+}
+
+#[test]
+fn epc_qr_payload() {
+    let bank = BankDetails {
+        name: "Banque".to_string(),
+        iban: "FR76 3000 6000 0112 3456 7890 189".to_string(),
+        bic: "agrifrpp".to_string(),
+    };
+    let payload = epc_payload("ACME SARL ", &bank, 1234.5, "EUR", "F00042").unwrap();
+    assert_eq!(
+        payload,
+        "BCD\n002\n1\nSCT\nAGRIFRPP\nACME SARL\nFR7630006000011234567890189\nEUR1234.50\n\n\nF00042"
+    );
+    assert!(epc_payload("ACME", &bank, 100.0, "CHF", "F00042").is_none());
+    assert!(epc_payload("ACME", &bank, 0.0, "EUR", "F00042").is_none());
+
+    let (path, svg) = qr_code_svg(&payload).unwrap();
+    assert_eq!(path, PathBuf::from(EPC_QR_ASSET));
+    assert!(String::from_utf8(svg).unwrap().starts_with("<?xml"));
+}
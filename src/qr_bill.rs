@@ -0,0 +1,440 @@
+use std::path::PathBuf;
+
+use qrcode::{Color, EcLevel, QrCode};
+
+use crate::codegen::sanitize;
+use crate::doctype::snapshot::QrBillSettings;
+use crate::errors::Errcode;
+use crate::identifiers::{check_iban, compact, mod97};
+use crate::lang::LangDict;
+
+/// Path of the QR code in the assets of the document
+pub const QR_BILL_ASSET: &str = "generated/qr_bill.svg";
+
+/// Largest amount of a QR-bill
+const QR_BILL_MAX_AMOUNT: f64 = 999_999_999.99;
+
+/// Check digits of the QR references, mod 10 recursive
+const MOD10_TABLE: [u32; 10] = [0, 9, 4, 6, 8, 2, 7, 1, 3, 5];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    /// 27 digits QR reference, only used with a QR-IBAN
+    Qrr(String),
+    /// ISO 11649 creditor reference, starting with RF
+    Scor(String),
+}
+
+impl Reference {
+    fn code(&self) -> &'static str {
+        match self {
+            Reference::Qrr(_) => "QRR",
+            Reference::Scor(_) => "SCOR",
+        }
+    }
+
+    fn value(&self) -> &str {
+        match self {
+            Reference::Qrr(r) | Reference::Scor(r) => r,
+        }
+    }
+
+    /// Reference in blocks, as printed on the payment part
+    fn display(&self) -> String {
+        match self {
+            Reference::Qrr(r) => {
+                let (head, tail) = r.split_at(2);
+                let mut blocks = vec![head.to_string()];
+                blocks.extend(in_blocks(tail, 5));
+                blocks.join(" ")
+            }
+            Reference::Scor(r) => in_blocks(r, 4).join(" "),
+        }
+    }
+}
+
+fn in_blocks(text: &str, size: usize) -> Vec<String> {
+    text.chars()
+        .collect::<Vec<char>>()
+        .chunks(size)
+        .map(|c| c.iter().collect())
+        .collect()
+}
+
+fn mod10_check_digit(digits: &str) -> u32 {
+    let carry = digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .fold(0, |carry, d| MOD10_TABLE[((carry + d) % 10) as usize]);
+    (10 - carry) % 10
+}
+
+/// QR reference of an invoice, from its internal id
+pub fn qr_reference(id: usize) -> Reference {
+    let digits = format!("{id:0>26}");
+    Reference::Qrr(format!("{digits}{}", mod10_check_digit(&digits)))
+}
+
+/// Creditor reference made from the letters and digits of the invoice number
+pub fn creditor_reference(number: &str) -> Reference {
+    let base: String = compact(number).chars().take(21).collect();
+    let check = 98 - mod97(&format!("{base}RF00"));
+    Reference::Scor(format!("RF{check:0>2}{base}"))
+}
+
+/// QR-IBANs are Swiss or Liechtenstein accounts with an institution id from 30000 to 31999
+pub fn is_qr_iban(iban: &str) -> bool {
+    let iban = compact(iban);
+    (iban.starts_with("CH") || iban.starts_with("LI"))
+        && iban
+            .get(4..9)
+            .and_then(|iid| iid.parse::<u32>().ok())
+            .is_some_and(|iid| (30000..=31999).contains(&iid))
+}
+
+/// IBAN the QR-bills are paid to, the one of the bank details if not set
+fn bill_iban(settings: &QrBillSettings, bank_iban: &str) -> String {
+    match settings.iban.as_str() {
+        "" => compact(bank_iban),
+        iban => compact(iban),
+    }
+}
+
+/// Checks the account and the address printed on the QR-bills, only Swiss and
+/// Liechtenstein accounts can be paid with them
+pub fn check_settings(settings: &QrBillSettings, bank_iban: &str) -> Result<(), String> {
+    let iban = bill_iban(settings, bank_iban);
+    if !iban.starts_with("CH") && !iban.starts_with("LI") {
+        return Err(format!(
+            "the IBAN {iban:?} is not a Swiss or Liechtenstein account"
+        ));
+    }
+    check_iban(&iban).map_err(|reason| format!("the IBAN {iban:?} is invalid, {reason}"))?;
+    for (name, value) in [("postcode", &settings.postcode), ("town", &settings.town)] {
+        if value.trim().is_empty() {
+            return Err(format!("the {name} of the creditor is not set"));
+        }
+    }
+    Ok(())
+}
+
+/// Structured address of the creditor
+#[derive(Debug, Clone)]
+pub struct Creditor {
+    pub name: String,
+    pub street: String,
+    pub building_number: String,
+    pub postcode: String,
+    pub town: String,
+    pub country: String,
+}
+
+/// Payment part of a Swiss QR-bill, the debtor is left blank to be filled by hand
+#[derive(Debug, Clone)]
+pub struct QrBill {
+    pub iban: String,
+    pub creditor: Creditor,
+    pub amount: f64,
+    pub currency: String,
+    pub reference: Reference,
+    /// Unstructured message, the invoice number
+    pub message: String,
+}
+
+impl QrBill {
+    /// QR-bill of an invoice, none if its currency or amount cannot be paid this way. The QR
+    /// reference is used with a QR-IBAN, the creditor reference with other accounts.
+    pub fn new(
+        settings: &QrBillSettings,
+        creditor_name: &str,
+        bank_iban: &str,
+        (id, number): (usize, &str),
+        amount: f64,
+        currency: &str,
+    ) -> Result<Option<QrBill>, Errcode> {
+        if !["CHF", "EUR"].contains(&currency) || !(0.01..=QR_BILL_MAX_AMOUNT).contains(&amount) {
+            return Ok(None);
+        }
+        check_settings(settings, bank_iban).map_err(|e| Errcode::InvalidConfig("qr_bill", e))?;
+        let iban = bill_iban(settings, bank_iban);
+        let reference = if is_qr_iban(&iban) {
+            qr_reference(id)
+        } else {
+            creditor_reference(number)
+        };
+        // Longest fields allowed by the guidelines
+        let setting = |value: &str, max| value.trim().chars().take(max).collect();
+        Ok(Some(QrBill {
+            iban,
+            creditor: Creditor {
+                name: creditor_name.chars().take(70).collect(),
+                street: setting(&settings.street, 70),
+                building_number: setting(&settings.building_number, 16),
+                postcode: setting(&settings.postcode, 16),
+                town: setting(&settings.town, 35),
+                country: setting(&settings.country, 2),
+            },
+            amount,
+            currency: currency.to_string(),
+            reference,
+            message: number.chars().take(140).collect(),
+        }))
+    }
+
+    /// Content of the QR code, version 2.0 of the Swiss Implementation Guidelines
+    pub fn payload(&self) -> String {
+        let c = &self.creditor;
+        let mut lines = vec!["SPC", "0200", "1", &self.iban];
+        lines.extend([
+            "S",
+            &c.name,
+            &c.street,
+            &c.building_number,
+            &c.postcode,
+            &c.town,
+            &c.country,
+        ]);
+        // Ultimate creditor, for future use
+        lines.extend([""; 7]);
+        let amount = format!("{:.2}", self.amount);
+        lines.extend([amount.as_str(), &self.currency]);
+        // Debtor
+        lines.extend([""; 7]);
+        lines.extend([
+            self.reference.code(),
+            self.reference.value(),
+            &self.message,
+            "EPD",
+        ]);
+        lines.join("\n")
+    }
+
+    /// SVG image of the QR code with the Swiss cross in its middle
+    pub fn qr_code_svg(&self) -> Result<(PathBuf, Vec<u8>), Errcode> {
+        let code = QrCode::with_error_correction_level(self.payload().as_bytes(), EcLevel::M)?;
+        let width = code.width();
+        let mut path = String::new();
+        for (idx, color) in code.to_colors().iter().enumerate() {
+            if *color == Color::Dark {
+                path += &format!("M{} {}h1v1h-1z", idx % width, idx / width);
+            }
+        }
+        // The cross is 7mm wide on a code of 46mm
+        let size = width as f64 * 7.0 / 46.0;
+        let origin = (width as f64 - size) / 2.0;
+        let unit = size / 32.0;
+        let image = format!(
+            r#"<?xml version="1.0" standalone="yes"?>
+<svg xmlns="http://www.w3.org/2000/svg" version="1.1" viewBox="0 0 {width} {width}" shape-rendering="crispEdges">
+<path fill="black" d="{path}"/>
+<g transform="translate({origin} {origin}) scale({unit})">
+<rect width="32" height="32" fill="white"/>
+<rect x="2" y="2" width="28" height="28" fill="black"/>
+<rect x="13.5" y="8" width="5" height="16" fill="white"/>
+<rect x="8" y="13.5" width="16" height="5" fill="white"/>
+</g>
+</svg>
+"#
+        );
+        Ok((PathBuf::from(QR_BILL_ASSET), image.into_bytes()))
+    }
+
+    fn display_iban(&self) -> String {
+        in_blocks(&self.iban, 4).join(" ")
+    }
+
+    /// Amount with a space between the thousands
+    fn display_amount(&self) -> String {
+        let amount = format!("{:.2}", self.amount);
+        let (units, cents) = amount.split_at(amount.len() - 3);
+        let units = units.chars().rev().collect::<String>();
+        let units = in_blocks(&units, 3)
+            .join(" ")
+            .chars()
+            .rev()
+            .collect::<String>();
+        format!("{units}{cents}")
+    }
+
+    fn display_creditor(&self) -> String {
+        let c = &self.creditor;
+        [
+            self.display_iban(),
+            c.name.clone(),
+            format!("{} {}", c.street, c.building_number)
+                .trim()
+                .to_string(),
+            format!("{}-{} {}", c.country, c.postcode, c.town),
+        ]
+        .iter()
+        .map(|line| sanitize(line))
+        .collect::<Vec<String>>()
+        .join(" \\ ")
+    }
+}
+
+/// Receipt and payment part, on a page of their own at the bottom of the A4 sheet
+pub fn generate_qr_bill(source: &mut String, lang: &LangDict, bill: &QrBill) {
+    let word = |w| lang.get_doctype_word("qr_bill", w);
+    let heading = |size: &str, w| format!("#text({size}, weight: \"bold\")[{}]", word(w));
+    let creditor = bill.display_creditor();
+    let reference = bill.reference.display();
+    let amount = bill.display_amount();
+    let currency = &bill.currency;
+    let blank = |width: &str, height: &str| {
+        format!(
+            "#rect(width: {width}, height: {height}, stroke: (dash: \"dashed\", thickness: 0.5pt))"
+        )
+    };
+
+    // Changing the page settings starts a new page
+    *source += "#set page(margin: 0pt, footer: none)\n";
+    *source += format!(
+        "#place(bottom + left, block(width: 210mm, height: 105mm, stroke: (top: (dash: \"dashed\", thickness: 0.5pt)))[
+    #text(6pt)[#h(1fr) {separate} #h(1fr)]
+    #place(top + left, block(width: 62mm, height: 105mm, inset: 5mm, stroke: (right: (dash: \"dashed\", thickness: 0.5pt)))[
+        #set text(8pt)
+        #set par(leading: 0.5em)
+        {receipt} \\
+        #v(2mm)
+        {account} \\ {creditor} \\
+        #v(2mm)
+        {reference_title} \\ {reference} \\
+        #v(2mm)
+        {payable_by} \\
+        {receipt_blank}
+        #place(top + left, dy: 63mm)[
+            #grid(columns: (20mm, auto), column-gutter: 2mm,
+                [{currency_title}], [{amount_title}],
+                [{currency}], [{amount}],
+            )
+        ]
+        #place(top + right, dy: 77mm)[{acceptance}]
+    ])
+    #place(top + left, dx: 62mm, block(width: 148mm, height: 105mm, inset: 5mm)[
+        #set text(10pt)
+        #set par(leading: 0.5em)
+        #grid(columns: (51mm, 1fr), column-gutter: 5mm,
+            [
+                {payment_part}
+                #v(5mm)
+                #image(\"{QR_BILL_ASSET}\", width: 46mm, height: 46mm)
+                #v(5mm)
+                #grid(columns: (20mm, auto), column-gutter: 2mm,
+                    [{currency_title_pp}], [{amount_title_pp}],
+                    [{currency}], [{amount}],
+                )
+            ],
+            [
+                {account_pp} \\ {creditor} \\
+                #v(2mm)
+                {reference_title_pp} \\ {reference} \\
+                #v(2mm)
+                {information} \\ {message} \\
+                #v(2mm)
+                {payable_by_pp} \\
+                {payment_blank}
+            ],
+        )
+    ])
+])\n",
+        separate = word("separate"),
+        receipt = heading("11pt", "receipt"),
+        account = heading("6pt", "account"),
+        reference_title = heading("6pt", "reference"),
+        payable_by = heading("6pt", "payable_by"),
+        receipt_blank = blank("52mm", "20mm"),
+        currency_title = heading("6pt", "currency"),
+        amount_title = heading("6pt", "amount"),
+        acceptance = heading("6pt", "acceptance_point"),
+        payment_part = heading("11pt", "payment_part"),
+        currency_title_pp = heading("8pt", "currency"),
+        amount_title_pp = heading("8pt", "amount"),
+        account_pp = heading("8pt", "account"),
+        reference_title_pp = heading("8pt", "reference"),
+        information = heading("8pt", "information"),
+        message = sanitize(&bill.message),
+        payable_by_pp = heading("8pt", "payable_by"),
+        payment_blank = blank("65mm", "25mm"),
+    )
+    .as_str();
+}
+
+#[test]
+fn qr_bill_references() {
+    assert_eq!(mod10_check_digit("21000000000313947143000901"), 7);
+    assert_eq!(
+        qr_reference(42),
+        Reference::Qrr("000000000000000000000000420".to_string())
+    );
+    assert_eq!(
+        qr_reference(42).display(),
+        "00 00000 00000 00000 00000 00420"
+    );
+    assert_eq!(
+        creditor_reference("5390 0754 7034"),
+        Reference::Scor("RF18539007547034".to_string())
+    );
+    assert_eq!(mod97("539007547034RF18"), 1);
+    assert!(is_qr_iban("CH44 3199 9123 0008 8901 2"));
+    assert!(!is_qr_iban("CH93 0076 2011 6238 5295 7"));
+
+    let cfg = QrBillSettings {
+        iban: String::new(),
+        street: "Rue".repeat(30),
+        building_number: String::new(),
+        postcode: "2501".to_string(),
+        town: "Biel".to_string(),
+        country: "CH".to_string(),
+    };
+    let bill = QrBill::new(
+        &cfg,
+        "Robert Schneider AG",
+        "CH44 3199 9123 0008 8901 2",
+        (42, "F00042"),
+        1949.75,
+        "CHF",
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(bill.creditor.street.len(), 70);
+    assert_eq!(bill.display_amount(), "1 949.75");
+    assert_eq!(bill.display_iban(), "CH44 3199 9123 0008 8901 2");
+    let payload = bill.payload();
+    let lines: Vec<&str> = payload.split('\n').collect();
+    assert_eq!(lines.len(), 31);
+    assert_eq!(lines[3], "CH4431999123000889012");
+    assert_eq!(lines[18..20], ["1949.75", "CHF"]);
+    assert_eq!(
+        lines[27..],
+        ["QRR", "000000000000000000000000420", "F00042", "EPD"]
+    );
+
+    let bill = QrBill::new(
+        &cfg,
+        "ACME",
+        "CH93 0076 2011 6238 5295 7",
+        (42, "F00042"),
+        10.0,
+        "EUR",
+    );
+    assert_eq!(bill.unwrap().unwrap().reference.code(), "SCOR");
+    let bill = |cfg, iban, currency| QrBill::new(cfg, "ACME", iban, (42, "F00042"), 10.0, currency);
+    assert!(bill(&cfg, "CH93 0076 2011 6238 5295 7", "USD")
+        .unwrap()
+        .is_none());
+
+    // Only Swiss accounts, and creditors with a full address, can be paid by QR-bill
+    assert!(bill(&cfg, "FR76 3000 6000 0112 3456 7890 189", "EUR").is_err());
+    assert!(bill(&cfg, "CH93 0076 2011 6238 5295 8", "CHF").is_err());
+    let no_postcode = QrBillSettings {
+        postcode: String::new(),
+        ..cfg.clone()
+    };
+    let Err(Errcode::InvalidConfig(_, msg)) =
+        bill(&no_postcode, "CH93 0076 2011 6238 5295 7", "CHF")
+    else {
+        panic!("A QR-bill needs the postcode of the creditor");
+    };
+    assert!(msg.contains("postcode"));
+}
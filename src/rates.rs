@@ -0,0 +1,198 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::currency::AmountFormat;
+use crate::data::write_atomic;
+use crate::errors::Errcode;
+
+/// Oldest rate used for a day, the ECB publishes no rate on week-ends and holidays
+const MAX_RATE_AGE_DAYS: u64 = 7;
+
+/// Exchange rates published by the ECB, in units of each currency for 1 EUR
+#[derive(Serialize, Deserialize, Default)]
+pub struct RateTable {
+    rates: BTreeMap<NaiveDate, HashMap<String, f64>>,
+}
+
+/// Rate used to convert the amounts of a document, saved with it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExchangeRate {
+    /// Currency the amounts are converted to
+    pub currency: String,
+    /// Units of `currency` for one unit of the currency of the document
+    pub rate: f64,
+    /// Day the rate was published
+    pub date: NaiveDate,
+}
+
+impl RateTable {
+    pub fn fname(datadir: &Path) -> PathBuf {
+        datadir.join("exchange_rates").with_extension("json")
+    }
+
+    pub fn load(datadir: &Path) -> Result<RateTable, Errcode> {
+        let fname = Self::fname(datadir);
+        if !fname.is_file() {
+            return Ok(RateTable::default());
+        }
+        serde_json::from_str(&std::fs::read_to_string(&fname)?)
+            .map_err(|e| Errcode::DataCorrupted(fname.display().to_string(), e.to_string()))
+    }
+
+    pub fn save(&self, datadir: &Path) -> Result<(), Errcode> {
+        write_atomic(
+            &Self::fname(datadir),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )
+    }
+
+    /// Adds the rates of an ECB reference rates file, either the CSV of the daily or
+    /// historical rates, or the XML of the daily or 90 days rates.
+    /// Returns the number of days imported.
+    pub fn import_ecb(&mut self, content: &str) -> Result<usize, Errcode> {
+        let days = if content.trim_start().starts_with('<') {
+            parse_ecb_xml(content)?
+        } else {
+            parse_ecb_csv(content)?
+        };
+        let nb_days = days.len();
+        for (date, rates) in days {
+            self.rates.entry(date).or_default().extend(rates);
+        }
+        Ok(nb_days)
+    }
+
+    /// Latest rate published on or before `date` to convert `from` to `to`, at most
+    /// `MAX_RATE_AGE_DAYS` earlier
+    pub fn rate(&self, from: &str, to: &str, date: NaiveDate) -> Result<ExchangeRate, Errcode> {
+        let per_eur = |rates: &HashMap<String, f64>, code: &str| {
+            if code == "EUR" {
+                Some(1.0)
+            } else {
+                rates.get(code).copied()
+            }
+        };
+        let oldest = date - Days::new(MAX_RATE_AGE_DAYS);
+        self.rates
+            .range(oldest..=date)
+            .rev()
+            .find_map(|(day, rates)| {
+                let rate = per_eur(rates, to)? / per_eur(rates, from)?;
+                Some(ExchangeRate {
+                    currency: to.to_string(),
+                    rate,
+                    date: *day,
+                })
+            })
+            .ok_or_else(|| {
+                Errcode::ExchangeRateMissing(from.to_string(), to.to_string(), date.to_string())
+            })
+    }
+}
+
+/// Amounts of a document converted with the rate saved with it
+pub struct Conversion<'a> {
+    pub rate: &'a ExchangeRate,
+    pub amounts: AmountFormat,
+}
+
+type EcbDays = Vec<(NaiveDate, HashMap<String, f64>)>;
+
+fn invalid_ecb(msg: String) -> Errcode {
+    Errcode::DataCorrupted("ECB rates file".to_string(), msg)
+}
+
+fn parse_ecb_date(date: &str) -> Result<NaiveDate, Errcode> {
+    let date = date.trim();
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%d %B %Y"))
+        .map_err(|_| invalid_ecb(format!("Invalid date {date:?}")))
+}
+
+fn parse_ecb_csv(content: &str) -> Result<EcbDays, Errcode> {
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid_ecb("Empty file".to_string()))?;
+    let currencies: Vec<&str> = header.split(',').map(|c| c.trim()).collect();
+    if currencies.first() != Some(&"Date") {
+        return Err(invalid_ecb(format!("Unknown header {header:?}")));
+    }
+
+    let mut days = vec![];
+    for line in lines {
+        let mut fields = line.split(',').map(|f| f.trim());
+        let date = parse_ecb_date(fields.next().unwrap())?;
+        let mut rates = HashMap::new();
+        for (code, rate) in currencies.iter().skip(1).zip(fields) {
+            // Currencies not quoted on that day are written N/A
+            if let (false, Ok(rate)) = (code.is_empty(), rate.parse::<f64>()) {
+                rates.insert(code.to_string(), rate);
+            }
+        }
+        days.push((date, rates));
+    }
+    Ok(days)
+}
+
+pub fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{name}="))? + name.len() + 1;
+    let quote = tag[start..].chars().next()?;
+    let value = &tag[start + 1..];
+    Some(&value[..value.find(quote)?])
+}
+
+fn parse_ecb_xml(content: &str) -> Result<EcbDays, Errcode> {
+    let mut days: EcbDays = vec![];
+    for tag in content.split('<').filter(|t| t.starts_with("Cube")) {
+        if let Some(date) = xml_attribute(tag, "time") {
+            days.push((parse_ecb_date(date)?, HashMap::new()));
+        } else if let (Some(code), Some(rate)) =
+            (xml_attribute(tag, "currency"), xml_attribute(tag, "rate"))
+        {
+            let (_, rates) = days
+                .last_mut()
+                .ok_or_else(|| invalid_ecb(format!("Rate of {code} outside of a day")))?;
+            let rate = rate
+                .parse()
+                .map_err(|_| invalid_ecb(format!("Invalid rate {rate:?} for {code}")))?;
+            rates.insert(code.to_string(), rate);
+        }
+    }
+    Ok(days)
+}
+
+#[test]
+fn ecb_rates_import() {
+    let mut table = RateTable::default();
+    let csv = "Date, USD, JPY, GBP, \n17 October 2026, 1.1000, 160.00, 0.8500, \n";
+    assert_eq!(table.import_ecb(csv).unwrap(), 1);
+    let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+        <gesmes:Envelope><Cube>
+        <Cube time='2026-10-19'>
+            <Cube currency='USD' rate='1.0000'/>
+            <Cube currency='GBP' rate='0.8000'/>
+        </Cube></Cube></gesmes:Envelope>";
+    assert_eq!(table.import_ecb(xml).unwrap(), 1);
+
+    let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+    // Week-ends use the rate of the last working day
+    let rate = table.rate("USD", "EUR", day(18)).unwrap();
+    assert_eq!(rate.date, day(17));
+    assert!((rate.rate - 1.0 / 1.1).abs() < 1e-9);
+    let rate = table.rate("GBP", "USD", day(20)).unwrap();
+    assert_eq!(rate.date, day(19));
+    assert!((rate.rate - 1.25).abs() < 1e-9);
+    assert!(table.rate("JPY", "EUR", day(19)).unwrap().date == day(17));
+    assert!(table.rate("USD", "EUR", day(16)).is_err());
+    // Rates older than a week are not used
+    assert!(table.rate("JPY", "EUR", day(24)).unwrap().date == day(17));
+    assert!(matches!(
+        table.rate("JPY", "EUR", day(25)),
+        Err(Errcode::ExchangeRateMissing(..))
+    ));
+    assert!(table.import_ecb("USD, GBP\n").is_err());
+}
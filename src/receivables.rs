@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::codegen::{generate_header, write_page_settings};
+use crate::config::ConfigStore;
+use crate::currency::AmountFormat;
+use crate::data::{Datastore, Date};
+use crate::doctype::snapshot::Issuer;
+use crate::errors::Errcode;
+use crate::lang::LangDict;
+use crate::listing::{csv_field, print_table};
+use crate::report::write_typst_table;
+
+/// Days late of each column, the first one is for the invoices not due yet
+const BUCKETS: [&str; 5] = ["current", "1-30", "31-60", "61-90", "90+"];
+
+fn bucket(days_late: i64) -> usize {
+    match days_late {
+        ..=0 => 0,
+        1..=30 => 1,
+        31..=60 => 2,
+        61..=90 => 3,
+        _ => 4,
+    }
+}
+
+/// Amount owed by a client in a currency, by how late the payment is
+#[derive(Serialize, Debug)]
+pub struct AgedRow {
+    /// None for the total of all the clients
+    pub client: Option<String>,
+    pub currency: String,
+    pub buckets: [f64; 5],
+    pub total: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AgedBalance {
+    pub as_of: NaiveDate,
+    pub rows: Vec<AgedRow>,
+    /// Totals per currency
+    pub totals: Vec<AgedRow>,
+    /// Invoices without a due date, their creation date could not be understood
+    pub undated: usize,
+}
+
+/// What the clients still owe on `as_of`, from the due dates of the invoices and the
+/// payments received until then
+pub fn aged_balance(
+    cfg: &ConfigStore,
+    data: &Datastore,
+    as_of: NaiveDate,
+    profile: &Option<String>,
+) -> AgedBalance {
+    let mut rows: BTreeMap<(String, String), [f64; 5]> = BTreeMap::new();
+    let mut totals: BTreeMap<String, [f64; 5]> = BTreeMap::new();
+    let mut undated = 0;
+    for inp in data.invoices.history.iter() {
+        if profile.is_some() && (inp.profile != *profile) {
+            continue;
+        }
+        let Some(due) = inp.payment_due(cfg) else {
+            undated += 1;
+            continue;
+        };
+        if matches!(inp.created, Date::Day(created) if created > as_of) {
+            continue;
+        }
+        let balance = inp.balance(Some(as_of));
+        if balance.abs() < 0.005 {
+            continue;
+        }
+        let col = bucket((as_of - due).num_days());
+        let currency = inp.currency_code(cfg);
+        rows.entry((inp.recipient.clone(), currency.clone()))
+            .or_default()[col] += balance;
+        totals.entry(currency).or_default()[col] += balance;
+    }
+
+    let row = |client, currency, buckets: [f64; 5]| AgedRow {
+        client,
+        currency,
+        buckets,
+        total: buckets.iter().sum(),
+    };
+    AgedBalance {
+        as_of,
+        rows: rows
+            .into_iter()
+            .map(|((client, currency), buckets)| row(Some(client), currency, buckets))
+            .collect(),
+        totals: totals
+            .into_iter()
+            .map(|(currency, buckets)| row(None, currency, buckets))
+            .collect(),
+        undated,
+    }
+}
+
+impl AgedBalance {
+    /// Cells of the clients then of the totals, amounts are written with `fmt_amount`
+    fn cells<F>(&self, total_name: &str, fmt_amount: F) -> Result<Vec<Vec<String>>, Errcode>
+    where
+        F: Fn(&str, f64) -> Result<String, Errcode>,
+    {
+        let mut res = vec![];
+        for row in self.rows.iter().chain(self.totals.iter()) {
+            let mut cells = vec![
+                row.client.clone().unwrap_or_else(|| total_name.to_string()),
+                row.currency.clone(),
+            ];
+            for amount in row.buckets.iter().chain([row.total].iter()) {
+                cells.push(fmt_amount(&row.currency, *amount)?);
+            }
+            res.push(cells);
+        }
+        Ok(res)
+    }
+
+    pub fn print_table(&self, lang: &LangDict) -> Result<(), Errcode> {
+        let rows = self.cells("TOTAL", |currency, amount| {
+            Ok(AmountFormat::new(lang, currency)?.format(amount))
+        })?;
+        let mut header = vec!["CLIENT", "CURRENCY"];
+        header.extend(BUCKETS.map(|b| if b == "current" { "CURRENT" } else { b }));
+        header.push("TOTAL");
+        print_table(&header, &rows, &[2, 3, 4, 5, 6, 7]);
+        Ok(())
+    }
+
+    pub fn print_csv(&self) -> Result<(), Errcode> {
+        let rows = self.cells("total", |_, amount| Ok(format!("{amount:.2}")))?;
+        let mut header = vec!["client", "currency"];
+        header.extend(BUCKETS);
+        header.push("total");
+        println!("{}", header.join(","));
+        for row in rows.iter() {
+            let row: Vec<String> = row.iter().map(|cell| csv_field(cell)).collect();
+            println!("{}", row.join(","));
+        }
+        Ok(())
+    }
+
+    /// Typst code of the aged balance, with the header of the issuer
+    pub fn typst_code(&self, cfg: &ConfigStore, lang: &LangDict) -> Result<String, Errcode> {
+        let word = |w| lang.get_doctype_word("receivables", w);
+        let days = word("days");
+        let mut header = vec![
+            lang.get_doctype_word("report", "client"),
+            lang.get_doctype_word("report", "currency"),
+            word("current"),
+        ];
+        header.extend(["1–30", "31–60", "61–90", "> 90"].map(|b| format!("{b} {days}")));
+        header.push(word("total"));
+        let rows = self.cells(&word("total"), |currency, amount| {
+            Ok(AmountFormat::new(lang, currency)?.format(amount))
+        })?;
+
+        let mut source = "".to_string();
+        write_page_settings(&mut source, "");
+        generate_header(&Issuer::from_config(cfg), &mut source);
+        source += "#v(sep_par())\n";
+        source += &format!(
+            "#align(center)[#text(17pt)[*{}*] \\ {} {}]\n",
+            word("title"),
+            word("as_of"),
+            lang.format_date(&self.as_of)?,
+        );
+        source += "#v(sep_par())\n";
+        write_typst_table(&mut source, &header, &rows);
+        Ok(source)
+    }
+}
+
+#[test]
+fn aged_buckets() {
+    use crate::contact::ContactBook;
+    use crate::doctype::quotation::QuotationSavedData;
+    use crate::rates::RateTable;
+
+    let data = Datastore {
+        contacts: ContactBook::default(),
+        invoices: serde_json::from_str(
+            r#"{"id_counter": 6, "history": [
+            {"id": 1, "recipient": "acme", "quote_nb": null, "date_sell": "2026-01-10",
+             "tx": [["Audit", 1.0, 1000.0]], "tax_rate": 0.2, "created": "2026-01-12",
+             "payments": [{"date": "2026-02-01", "amount": 200.0},
+                          {"date": "2026-05-01", "amount": 1000.0}]},
+            {"id": 2, "recipient": "acme", "quote_nb": null, "date_sell": "2026-03-02",
+             "tx": [["Support", 1.0, 100.0]], "tax_rate": null, "created": "2026-03-02",
+             "due_date": "2026-03-31"},
+            {"id": 3, "recipient": "initech", "quote_nb": null, "date_sell": "2026-03-20",
+             "tx": [["Audit", 1.0, 500.0]], "tax_rate": null, "created": "2026-03-20"},
+            {"id": 4, "recipient": "initech", "quote_nb": null, "date_sell": "2026-04-10",
+             "tx": [["Audit", 1.0, 50.0]], "tax_rate": null, "created": "2026-04-10"},
+            {"id": 5, "recipient": "acme", "quote_nb": null, "date_sell": "?",
+             "tx": [["Audit", 1.0, 50.0]], "tax_rate": null, "created": "some day"}]}"#,
+        )
+        .unwrap(),
+        quotations: QuotationSavedData::init(),
+        audit: vec![],
+        rates: RateTable::default(),
+    };
+    let cfg: ConfigStore = toml::from_str(include_str!("../default/config.toml")).unwrap();
+    let as_of = NaiveDate::from_ymd_opt(2026, 4, 15).unwrap();
+    let aged = aged_balance(&cfg, &data, as_of, &None);
+    assert_eq!(aged.undated, 1);
+    assert_eq!(aged.rows.len(), 2);
+    // Invoice 1 was due on 2026-02-11, the second payment was received later
+    assert_eq!(aged.rows[0].buckets, [0.0, 100.0, 0.0, 1000.0, 0.0]);
+    assert_eq!(aged.rows[1].buckets, [550.0, 0.0, 0.0, 0.0, 0.0]);
+    assert_eq!(aged.totals[0].total, 1650.0);
+
+    let aged = aged_balance(
+        &cfg,
+        &data,
+        NaiveDate::from_ymd_opt(2026, 5, 1).unwrap(),
+        &None,
+    );
+    assert_eq!(aged.rows[0].buckets, [0.0, 0.0, 100.0, 0.0, 0.0]);
+    assert_eq!(aged.rows[1].buckets, [50.0, 500.0, 0.0, 0.0, 0.0]);
+}
@@ -0,0 +1,360 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::audit::AuditEvent;
+use crate::bank::BankCredit;
+use crate::config::ConfigStore;
+use crate::currency::AmountFormat;
+use crate::data::Datastore;
+use crate::doctype::invoice::Payment;
+use crate::errors::Errcode;
+use crate::lang::LangDict;
+
+const SCORE_NUMBER: u32 = 5;
+const SCORE_AMOUNT: u32 = 3;
+const SCORE_NAME: u32 = 2;
+/// An invoice is proposed for its number, or for its amount paid by the client
+const SCORE_MIN: u32 = SCORE_AMOUNT + SCORE_NAME;
+
+/// Open invoice that a credit could pay
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub invoice: usize,
+    pub number: String,
+    pub recipient: String,
+    pub currency: String,
+    /// Left to pay before this credit
+    pub balance: f64,
+    /// Left to pay before the credits of the statement
+    pub open_balance: f64,
+    pub score: u32,
+}
+
+/// Credit of the bank statement, with the invoices it could pay from the best match to the worst
+#[derive(Debug)]
+pub struct Proposal {
+    pub credit: BankCredit,
+    pub candidates: Vec<Candidate>,
+    /// Candidate accepted, none to leave the credit unmatched
+    pub selected: Option<usize>,
+}
+
+impl Proposal {
+    pub fn accepted(&self) -> Option<&Candidate> {
+        self.selected.map(|idx| &self.candidates[idx])
+    }
+
+    /// Proposes the next candidate, then no invoice at all after the last one
+    pub fn next_candidate(&mut self) {
+        self.selected = match self.selected {
+            None if !self.candidates.is_empty() => Some(0),
+            Some(idx) if idx + 1 < self.candidates.len() => Some(idx + 1),
+            _ => None,
+        };
+    }
+
+    pub fn previous_candidate(&mut self) {
+        self.selected = match self.selected {
+            None => self.candidates.len().checked_sub(1),
+            Some(0) => None,
+            Some(idx) => Some(idx - 1),
+        };
+    }
+
+    pub fn display(&self, lang: &LangDict) -> Result<String, Errcode> {
+        let credit = &self.credit;
+        let amounts = |currency: &str| AmountFormat::new(lang, currency);
+        let amount = match credit.currency {
+            Some(ref currency) => amounts(currency)?.format(credit.amount),
+            None => format!("{:.2}", credit.amount),
+        };
+        let matched = match self.accepted() {
+            Some(c) => format!(
+                "{} {} ({} / {})",
+                c.number,
+                c.recipient,
+                amounts(&c.currency)?.format(c.balance),
+                c.score
+            ),
+            None => "-".to_string(),
+        };
+        Ok(format!(
+            "{}  {amount}  {}  {:?}  ->  {matched}",
+            credit.date, credit.payer, credit.reference
+        ))
+    }
+}
+
+/// Letters and digits of a text, in upper case
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_uppercase())
+        .collect()
+}
+
+/// The number is found in the reference, and not preceded or followed by another digit
+fn reference_contains(reference: &str, number: &str) -> bool {
+    let (reference, number) = (normalize(reference), normalize(number));
+    if number.is_empty() {
+        return false;
+    }
+    reference.match_indices(&number).any(|(pos, _)| {
+        let before = reference[..pos].chars().next_back();
+        let after = reference[pos + number.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_digit()) && !after.is_some_and(|c| c.is_ascii_digit())
+    })
+}
+
+/// A significant word of the name of the client is in the name of the payer
+fn same_payer(payer: &str, name: &str) -> bool {
+    const LEGAL_FORMS: [&str; 9] = [
+        "SA", "SAS", "SARL", "EURL", "GMBH", "LTD", "INC", "LLC", "CO",
+    ];
+    let words = |text: &str| -> HashSet<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .map(|w| w.to_uppercase())
+            .filter(|w| (w.chars().count() >= 3) && !LEGAL_FORMS.contains(&w.as_str()))
+            .collect()
+    };
+    !words(payer).is_disjoint(&words(name))
+}
+
+/// Candidates of each credit of a statement, the credits already recorded as payments are
+/// left out. An invoice is proposed when the best score is reached by it alone.
+pub fn propose_matches(
+    cfg: &ConfigStore,
+    data: &Datastore,
+    credits: Vec<BankCredit>,
+    profile: &Option<String>,
+) -> (Vec<Proposal>, usize) {
+    let recorded: HashSet<&String> = data
+        .invoices
+        .history
+        .iter()
+        .flat_map(|inp| inp.payments.iter())
+        .filter_map(|p| p.bank_id.as_ref())
+        .collect();
+    let mut balances: HashMap<usize, f64> = data
+        .invoices
+        .history
+        .iter()
+        .map(|inp| (inp.id, inp.balance(None)))
+        .collect();
+    let balances_before = balances.clone();
+
+    let mut proposals = vec![];
+    let mut nb_recorded = 0;
+    for credit in credits {
+        if recorded.contains(&credit.id) {
+            nb_recorded += 1;
+            continue;
+        }
+        let mut candidates = vec![];
+        for inp in data.invoices.history.iter() {
+            let balance = balances[&inp.id];
+            let currency = inp.currency_code(cfg);
+            if (profile.is_some() && (inp.profile != *profile))
+                || (balance < 0.005)
+                || credit.currency.as_ref().is_some_and(|c| *c != currency)
+            {
+                continue;
+            }
+            let number = inp.display_number(cfg);
+            let name = if data.contacts.contains(&inp.recipient) {
+                data.contacts.get(&inp.recipient).name.clone()
+            } else {
+                inp.recipient.clone()
+            };
+            let mut score = 0;
+            if reference_contains(&credit.reference, &number) {
+                score += SCORE_NUMBER;
+            }
+            if (credit.amount - balance).abs() < 0.005 {
+                score += SCORE_AMOUNT;
+            }
+            if same_payer(&credit.payer, &name) {
+                score += SCORE_NAME;
+            }
+            if score > 0 {
+                candidates.push(Candidate {
+                    invoice: inp.id,
+                    number,
+                    recipient: inp.recipient.clone(),
+                    currency,
+                    balance,
+                    open_balance: balances_before[&inp.id],
+                    score,
+                });
+            }
+        }
+        candidates.sort_by(|a, b| b.score.cmp(&a.score).then(a.invoice.cmp(&b.invoice)));
+        let best = candidates.first().map(|c| c.score).unwrap_or(0);
+        let ties = candidates.iter().filter(|c| c.score == best).count();
+        let selected = ((best >= SCORE_MIN) && (ties == 1)).then_some(0);
+        if let Some(c) = selected.map(|idx| &candidates[idx]) {
+            *balances.get_mut(&c.invoice).unwrap() -= credit.amount;
+        }
+        proposals.push(Proposal {
+            credit,
+            candidates,
+            selected,
+        });
+    }
+    (proposals, nb_recorded)
+}
+
+/// Sets the balance of each candidate to what is left to pay before its credit, once the
+/// credits before it are recorded, after the accepted matches were changed
+pub fn update_balances(proposals: &mut [Proposal]) {
+    let mut paid: HashMap<usize, f64> = HashMap::new();
+    for proposal in proposals.iter_mut() {
+        for candidate in proposal.candidates.iter_mut() {
+            candidate.balance =
+                candidate.open_balance - paid.get(&candidate.invoice).copied().unwrap_or(0.0);
+        }
+        if let Some(idx) = proposal.selected {
+            let invoice = proposal.candidates[idx].invoice;
+            *paid.entry(invoice).or_default() += proposal.credit.amount;
+        }
+    }
+}
+
+/// Records the accepted matches as payments, returns how many were recorded and the
+/// invoices paid more than their total, with the amount paid in excess
+pub fn record_matches(
+    data: &mut Datastore,
+    proposals: &[Proposal],
+) -> (usize, Vec<(Candidate, f64)>) {
+    let mut nb = 0;
+    let mut overpaid = vec![];
+    for proposal in proposals.iter() {
+        let Some(candidate) = proposal.accepted() else {
+            continue;
+        };
+        let credit = &proposal.credit;
+        let Some(inp) = data
+            .invoices
+            .history
+            .iter_mut()
+            .find(|inp| inp.id == candidate.invoice)
+        else {
+            continue;
+        };
+        let reference = [credit.payer.as_str(), credit.reference.as_str()]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(" – ");
+        inp.payments.push(Payment {
+            date: credit.date,
+            amount: credit.amount,
+            reference: Some(reference).filter(|r| !r.is_empty()),
+            bank_id: Some(credit.id.clone()),
+        });
+        let balance = inp.balance(None);
+        data.audit.push(AuditEvent::PaymentRecorded {
+            invoice: inp.id,
+            amount: credit.amount,
+            date: credit.date,
+        });
+        nb += 1;
+        // Only the last credit paying too much is reported
+        overpaid.retain(|(c, _): &(Candidate, f64)| c.invoice != candidate.invoice);
+        if balance < -0.005 {
+            overpaid.push((candidate.clone(), -balance));
+        }
+    }
+    (nb, overpaid)
+}
+
+#[test]
+fn reconcile_credits() {
+    use crate::doctype::quotation::QuotationSavedData;
+    use crate::rates::RateTable;
+    use chrono::NaiveDate;
+
+    let mut data = Datastore {
+        contacts: serde_json::from_str(
+            r#"{"acme": {"slug": "acme", "name": "ACME SA", "address": "Paris",
+                "invoices": [1, 2], "quotations": []},
+                "initech": {"slug": "initech", "name": "Initech", "address": "Austin",
+                "invoices": [3], "quotations": []}}"#,
+        )
+        .unwrap(),
+        invoices: serde_json::from_str(
+            r#"{"id_counter": 4, "history": [
+            {"id": 1, "recipient": "acme", "quote_nb": null, "date_sell": "2026-01-10",
+             "tx": [["Audit", 1.0, 1000.0]], "tax_rate": 0.2, "created": "2026-01-12",
+             "number": "F00001"},
+            {"id": 2, "recipient": "acme", "quote_nb": null, "date_sell": "2026-02-02",
+             "tx": [["Support", 1.0, 300.0]], "tax_rate": null, "created": "2026-02-03",
+             "number": "F00002"},
+            {"id": 3, "recipient": "initech", "quote_nb": null, "date_sell": "2026-02-02",
+             "tx": [["Audit", 1.0, 300.0]], "tax_rate": null, "created": "2026-02-10",
+             "number": "F00003", "payments": [{"date": "2026-02-11", "amount": 300.0,
+             "bank_id": "BK-0"}]}]}"#,
+        )
+        .unwrap(),
+        quotations: QuotationSavedData::init(),
+        audit: vec![],
+        rates: RateTable::default(),
+    };
+    let cfg: ConfigStore = toml::from_str(include_str!("../default/config.toml")).unwrap();
+    let credit = |id: &str, amount, payer: &str, reference: &str| BankCredit {
+        id: id.to_string(),
+        date: NaiveDate::from_ymd_opt(2026, 3, 1).unwrap(),
+        amount,
+        currency: Some("EUR".to_string()),
+        payer: payer.to_string(),
+        reference: reference.to_string(),
+    };
+    let credits = vec![
+        credit("BK-0", 300.0, "INITECH", "F00003"),
+        // Partial payment, the number is enough
+        credit("BK-1", 500.0, "ACME", "Facture F-00001"),
+        // Same amount as invoice 2, but from another payer
+        credit("BK-2", 300.0, "Umbrella Corp", "Services"),
+        // Pays the rest of invoice 1
+        credit("BK-3", 700.0, "M. ACME", "Solde"),
+        // Not the number of invoice 2, but its amount paid by the client
+        credit("BK-4", 300.0, "ACME SA", "F000023"),
+    ];
+    let (mut proposals, recorded) = propose_matches(&cfg, &data, credits, &None);
+    assert_eq!(recorded, 1);
+    let selected: Vec<Option<usize>> = proposals
+        .iter()
+        .map(|p| p.accepted().map(|c| c.invoice))
+        .collect();
+    assert_eq!(selected, [Some(1), None, Some(1), Some(2)]);
+    assert_eq!(proposals[2].candidates[0].balance, 700.0);
+
+    proposals[1].next_candidate();
+    assert_eq!(proposals[1].accepted().unwrap().invoice, 2);
+    // Invoice 2 would be paid by this credit before the last one, which has the same amount
+    update_balances(&mut proposals);
+    assert_eq!(proposals[3].candidates[0].balance, 0.0);
+    proposals[1].next_candidate();
+    assert_eq!(proposals[1].selected, None);
+    proposals[1].previous_candidate();
+    assert_eq!(proposals[1].selected, Some(0));
+    proposals[1].selected = None;
+    update_balances(&mut proposals);
+    assert_eq!(proposals[3].candidates[0].balance, 300.0);
+
+    assert_eq!(record_matches(&mut data, &proposals).0, 3);
+    assert!(data.invoices.history[0].balance(None).abs() < 1e-9);
+    let payment = &data.invoices.history[1].payments[0];
+    assert_eq!(payment.bank_id.as_deref(), Some("BK-4"));
+    assert_eq!(payment.reference.as_deref(), Some("ACME SA – F000023"));
+    assert_eq!(data.audit.len(), 3);
+
+    // Recording the last credit again pays too much
+    let (_, overpaid) = record_matches(&mut data, &proposals[3..]);
+    assert_eq!(overpaid.len(), 1);
+    assert_eq!((overpaid[0].0.invoice, overpaid[0].1), (2, 300.0));
+
+    assert!(reference_contains("Facture 1 et 2", "1"));
+    assert!(!reference_contains("Facture 21", "1"));
+    assert!(!reference_contains("Facture 12", "1"));
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::codegen::{generate_header, sanitize, write_page_settings};
+use crate::config::ConfigStore;
+use crate::currency::AmountFormat;
+use crate::data::{Datastore, Date};
+use crate::doctype::snapshot::Issuer;
+use crate::errors::Errcode;
+use crate::lang::LangDict;
+use crate::listing::{csv_field, print_table};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Period {
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Period {
+    fn label(&self, date: &NaiveDate) -> String {
+        match self {
+            Period::Month => date.format("%Y-%m").to_string(),
+            Period::Quarter => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+            Period::Year => date.year().to_string(),
+        }
+    }
+}
+
+/// When the revenue is counted
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Basis {
+    /// On the day the invoice is created
+    Accrual,
+    /// On the days the payments are received, the tax in proportion to the amount paid
+    Cash,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReportFormat {
+    Table,
+    Csv,
+    Json,
+    Pdf,
+}
+
+/// Which invoices are reported
+pub struct ReportSettings {
+    pub period: Period,
+    pub basis: Basis,
+    pub by_client: bool,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub profile: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TaxTotal {
+    /// Rate, 0.2 for 20%
+    pub rate: f64,
+    pub amount: f64,
+}
+
+/// Totals of the invoices of a period, in a currency, for a client if the report is per client
+#[derive(Serialize, Debug)]
+pub struct ReportRow {
+    pub period: String,
+    pub client: Option<String>,
+    pub currency: String,
+    /// Invoices counted, or payments on a cash basis
+    pub count: usize,
+    pub total_no_tax: f64,
+    /// Tax collected, per rate
+    pub taxes: Vec<TaxTotal>,
+    pub total_with_tax: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub rows: Vec<ReportRow>,
+    /// Every tax rate of the report, a column is written for each of them
+    pub rates: Vec<f64>,
+    /// Invoices with a creation date entered as text that could not be understood, they
+    /// are only left out on an accrual basis
+    pub undated: usize,
+}
+
+// Rates are grouped in hundredths of a percent, so they can be compared
+fn rate_key(rate: f64) -> i64 {
+    (rate * 10000.0).round() as i64
+}
+
+/// Totals of the issued invoices, or of the payments received on a cash basis, the
+/// invoices with a negative total are subtracted
+pub fn build_report(cfg: &ConfigStore, data: &Datastore, settings: &ReportSettings) -> Report {
+    type Totals = (usize, f64, BTreeMap<i64, f64>);
+    let mut groups: BTreeMap<(String, Option<String>, String), Totals> = BTreeMap::new();
+    let mut undated = 0;
+    for inp in data.invoices.history.iter() {
+        if settings.profile.is_some() && (inp.profile != settings.profile) {
+            continue;
+        }
+        // Amounts without tax of the invoice, with the day they are counted on
+        let amounts: Vec<(NaiveDate, f64)> = match settings.basis {
+            Basis::Accrual => {
+                let Date::Day(created) = inp.created else {
+                    undated += 1;
+                    continue;
+                };
+                vec![(created, inp.total_no_tax())]
+            }
+            Basis::Cash => {
+                let total_with_tax = inp.total_with_tax();
+                if total_with_tax.abs() < 0.005 {
+                    continue;
+                }
+                inp.payments
+                    .iter()
+                    .map(|p| (p.date, inp.total_no_tax() * p.amount / total_with_tax))
+                    .collect()
+            }
+        };
+        for (day, total_no_tax) in amounts {
+            if settings.from.is_some_and(|from| day < from)
+                || settings.to.is_some_and(|to| day > to)
+            {
+                continue;
+            }
+            let client = settings.by_client.then(|| inp.recipient.clone());
+            let key = (settings.period.label(&day), client, inp.currency_code(cfg));
+            let (count, total, taxes) = groups.entry(key).or_default();
+            *count += 1;
+            *total += total_no_tax;
+            if let Some(rate) = inp.tax_rate {
+                *taxes.entry(rate_key(rate)).or_default() += total_no_tax * rate;
+            }
+        }
+    }
+
+    let mut rates: Vec<i64> = groups
+        .values()
+        .flat_map(|(_, _, taxes)| taxes.keys().copied())
+        .collect();
+    rates.sort();
+    rates.dedup();
+    let rows = groups
+        .into_iter()
+        .map(|((period, client, currency), (count, total, taxes))| {
+            let tax_total: f64 = taxes.values().sum();
+            ReportRow {
+                period,
+                client,
+                currency,
+                count,
+                total_no_tax: total,
+                taxes: taxes
+                    .into_iter()
+                    .map(|(rate, amount)| TaxTotal {
+                        rate: rate as f64 / 10000.0,
+                        amount,
+                    })
+                    .collect(),
+                total_with_tax: total + tax_total,
+            }
+        })
+        .collect();
+    Report {
+        rows,
+        rates: rates.into_iter().map(|r| r as f64 / 10000.0).collect(),
+        undated,
+    }
+}
+
+impl ReportRow {
+    fn tax(&self, rate: f64) -> Option<f64> {
+        self.taxes
+            .iter()
+            .find(|tax| rate_key(tax.rate) == rate_key(rate))
+            .map(|tax| tax.amount)
+    }
+}
+
+impl Report {
+    fn has_clients(&self) -> bool {
+        self.rows.iter().any(|row| row.client.is_some())
+    }
+
+    /// Header and cells of the report, amounts are written with `fmt_amount`
+    fn cells<F>(&self, tax_name: &str, fmt_amount: F) -> Result<Table, Errcode>
+    where
+        F: Fn(&str, f64) -> Result<String, Errcode>,
+    {
+        let mut header = vec!["period".to_string()];
+        if self.has_clients() {
+            header.push("client".to_string());
+        }
+        header.extend(["currency", "count", "total_no_tax"].map(String::from));
+        for rate in self.rates.iter() {
+            header.push(format!("{tax_name} {}%", rate_key(*rate) as f64 / 100.0));
+        }
+        header.push("total_with_tax".to_string());
+
+        let mut rows = vec![];
+        for row in self.rows.iter() {
+            let mut cells = vec![row.period.clone()];
+            if let Some(ref client) = row.client {
+                cells.push(client.clone());
+            }
+            cells.push(row.currency.clone());
+            cells.push(row.count.to_string());
+            cells.push(fmt_amount(&row.currency, row.total_no_tax)?);
+            for rate in self.rates.iter() {
+                cells.push(match row.tax(*rate) {
+                    Some(amount) => fmt_amount(&row.currency, amount)?,
+                    None => "".to_string(),
+                });
+            }
+            cells.push(fmt_amount(&row.currency, row.total_with_tax)?);
+            rows.push(cells);
+        }
+        Ok((header, rows))
+    }
+
+    pub fn print_table(&self, lang: &LangDict) -> Result<(), Errcode> {
+        let (header, rows) = self.cells("tax", |currency, amount| {
+            Ok(AmountFormat::new(lang, currency)?.format(amount))
+        })?;
+        let header: Vec<String> = header.iter().map(|h| h.to_uppercase()).collect();
+        let header: Vec<&str> = header.iter().map(|h| h.as_str()).collect();
+        let first_amount = header.len() - self.rates.len() - 3;
+        let right: Vec<usize> = (first_amount..header.len()).collect();
+        print_table(&header, &rows, &right);
+        Ok(())
+    }
+
+    pub fn print_csv(&self) -> Result<(), Errcode> {
+        let (header, rows) = self.cells("tax", |_, amount| Ok(format!("{amount:.2}")))?;
+        println!("{}", header.join(","));
+        for row in rows.iter() {
+            let row: Vec<String> = row.iter().map(|cell| csv_field(cell)).collect();
+            println!("{}", row.join(","));
+        }
+        Ok(())
+    }
+
+    /// Typst code of the report, with the header of the issuer
+    pub fn typst_code(
+        &self,
+        cfg: &ConfigStore,
+        lang: &LangDict,
+        settings: &ReportSettings,
+    ) -> Result<String, Errcode> {
+        let word = |w| lang.get_doctype_word("report", w);
+        let tax_name = lang.get_doctype_word("general", "tax_name");
+        let (mut header, rows) = self.cells(&tax_name, |currency, amount| {
+            Ok(AmountFormat::new(lang, currency)?.format(amount))
+        })?;
+        for cell in header.iter_mut() {
+            *cell = match cell.as_str() {
+                "period" => word("period"),
+                "client" => word("client"),
+                "currency" => word("currency"),
+                "count" => word("count"),
+                "total_no_tax" => lang.get_doctype_word("general", "total_price_no_tax"),
+                "total_with_tax" => lang.get_doctype_word("general", "total_price_with_tax"),
+                _ => cell.clone(),
+            };
+        }
+
+        let mut source = "".to_string();
+        write_page_settings(&mut source, "");
+        generate_header(&Issuer::from_config(cfg), &mut source);
+        source += "#v(sep_par())\n";
+        let range = match (settings.from, settings.to) {
+            (None, None) => "".to_string(),
+            (from, to) => format!(
+                " \\ {} – {}",
+                from.map(|d| lang.format_date(&d))
+                    .transpose()?
+                    .unwrap_or_default(),
+                to.map(|d| lang.format_date(&d))
+                    .transpose()?
+                    .unwrap_or_default(),
+            ),
+        };
+        source += &format!(
+            "#align(center)[#text(17pt)[*{}*]{range} \\ {} {}]\n",
+            word("title"),
+            word("generated"),
+            lang.format_date(&Utc::now().date_naive())?,
+        );
+        source += "#v(sep_par())\n";
+        write_typst_table(&mut source, &header, &rows);
+        Ok(source)
+    }
+}
+
+/// Table of the reports, with the header in bold
+pub fn write_typst_table(source: &mut String, header: &[String], rows: &[Vec<String>]) {
+    *source += &format!(
+        "#table(\n    stroke: table_color(),\n    columns: {},\n",
+        header.len()
+    );
+    let cells = header
+        .iter()
+        .map(|h| format!("[*{}*]", sanitize(h)))
+        .chain(rows.iter().flatten().map(|c| format!("[{}]", sanitize(c))));
+    for cell in cells {
+        *source += &format!("    {cell},\n");
+    }
+    *source += ")\n";
+}
+
+type Table = (Vec<String>, Vec<Vec<String>>);
+
+#[test]
+fn report_totals() {
+    use crate::contact::ContactBook;
+    use crate::doctype::quotation::QuotationSavedData;
+    use crate::rates::RateTable;
+
+    let data = Datastore {
+        contacts: ContactBook::default(),
+        invoices: serde_json::from_str(
+            r#"{"id_counter": 5, "history": [
+            {"id": 1, "recipient": "acme", "quote_nb": null, "date_sell": "2026-01-10",
+             "tx": [["Audit", 2.0, 500.0]], "tax_rate": 0.2, "created": "2026-01-12",
+             "payments": [{"date": "2026-02-15", "amount": 600.0},
+             {"date": "2026-04-02", "amount": 600.0}]},
+            {"id": 2, "recipient": "initech", "quote_nb": null, "date_sell": "2026-02-02",
+             "tx": [["Support", 1.0, 100.0]], "tax_rate": 0.055, "created": "2026-02-03"},
+            {"id": 3, "recipient": "acme", "quote_nb": null, "date_sell": "2026-03-01",
+             "tx": [["Refund", 1.0, -200.0]], "tax_rate": 0.2, "created": "2026-03-30"},
+            {"id": 4, "recipient": "acme", "quote_nb": null, "date_sell": "?",
+             "tx": [["Audit", 1.0, 50.0]], "tax_rate": null, "created": "some day"}]}"#,
+        )
+        .unwrap(),
+        quotations: QuotationSavedData::init(),
+        audit: vec![],
+        rates: RateTable::default(),
+    };
+    let cfg: ConfigStore = toml::from_str(include_str!("../default/config.toml")).unwrap();
+    let mut settings = ReportSettings {
+        period: Period::Quarter,
+        basis: Basis::Accrual,
+        by_client: false,
+        from: None,
+        to: None,
+        profile: None,
+    };
+    let report = build_report(&cfg, &data, &settings);
+    assert_eq!(report.undated, 1);
+    assert_eq!(report.rates, [0.055, 0.2]);
+    assert_eq!(report.rows.len(), 1);
+    let row = &report.rows[0];
+    assert_eq!((row.period.as_str(), row.count), ("2026-Q1", 3));
+    assert!((row.total_no_tax - 900.0).abs() < 1e-9);
+    assert!((row.tax(0.2).unwrap() - 160.0).abs() < 1e-9);
+    assert!((row.total_with_tax - 1065.5).abs() < 1e-9);
+
+    settings.period = Period::Month;
+    settings.by_client = true;
+    settings.to = NaiveDate::from_ymd_opt(2026, 2, 28);
+    let report = build_report(&cfg, &data, &settings);
+    let rows: Vec<(&str, Option<&str>)> = report
+        .rows
+        .iter()
+        .map(|row| (row.period.as_str(), row.client.as_deref()))
+        .collect();
+    assert_eq!(
+        rows,
+        [("2026-01", Some("acme")), ("2026-02", Some("initech"))]
+    );
+    assert_eq!(report.rows[1].tax(0.2), None);
+
+    // Half of the first invoice is paid in each quarter, the others are not paid
+    settings.period = Period::Quarter;
+    settings.basis = Basis::Cash;
+    settings.by_client = false;
+    settings.to = None;
+    let report = build_report(&cfg, &data, &settings);
+    assert_eq!(report.undated, 0);
+    assert_eq!(report.rows.len(), 2);
+    for (row, period) in report.rows.iter().zip(["2026-Q1", "2026-Q2"]) {
+        assert_eq!((row.period.as_str(), row.count), (period, 1));
+        assert!((row.total_no_tax - 500.0).abs() < 1e-9);
+        assert!((row.tax(0.2).unwrap() - 100.0).abs() < 1e-9);
+        assert!((row.total_with_tax - 600.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::contact::ContactBook;
+use crate::data::{commit_files, write_atomic, Datastore};
+use crate::doctype::invoice::InvoiceSavedData;
+use crate::doctype::quotation::QuotationSavedData;
+use crate::doctype::DocumentType;
+use crate::errors::Errcode;
+
+use super::schema::{DataKind, BASE_VERSION};
+use super::{backup_file, Storage};
+
+/// One JSON file per kind of data, rewritten on each save
+pub struct JsonStorage {
+    root: PathBuf,
+    /// Opened without the lock of the data directory, the files are never written
+    read_only: bool,
+}
+
+impl JsonStorage {
+    pub fn new(root: &Path) -> JsonStorage {
+        JsonStorage {
+            root: root.to_path_buf(),
+            read_only: false,
+        }
+    }
+
+    /// Storage to read the data while another process may change it, the data of older
+    /// versions is migrated in memory only
+    pub fn read_only(root: &Path) -> JsonStorage {
+        JsonStorage {
+            read_only: true,
+            ..JsonStorage::new(root)
+        }
+    }
+
+    fn fname(&self, kind: DataKind) -> PathBuf {
+        match kind {
+            DataKind::Contacts => ContactBook::fname(&self.root),
+            DataKind::Invoices => DocumentType::Invoice.fname(&self.root),
+            DataKind::Quotations => DocumentType::Quotation.fname(&self.root),
+        }
+    }
+}
+
+fn serialize<T: Serialize>(kind: DataKind, data: &T) -> Result<String, Errcode> {
+    let mut file = serde_json::Map::new();
+    file.insert("schema_version".to_string(), kind.version().into());
+    file.insert("data".to_string(), serde_json::to_value(data)?);
+    Ok(serde_json::to_string_pretty(&file)?)
+}
+
+/// Loads a data file, migrating it to the current version if needed.
+/// Fails if the data cannot be parsed, so it never gets overwritten.
+fn load<T: DeserializeOwned + Serialize>(
+    fname: &Path,
+    kind: DataKind,
+    read_only: bool,
+) -> Result<Option<T>, Errcode> {
+    if !fname.is_file() {
+        return Ok(None);
+    }
+    let corrupted =
+        |e: serde_json::Error| Errcode::DataCorrupted(fname.display().to_string(), e.to_string());
+    let content: Value =
+        serde_json::from_str(&std::fs::read_to_string(fname)?).map_err(corrupted)?;
+
+    // Files written before the versioning have their data at the top level
+    let (version, mut data) = match content {
+        Value::Object(mut file)
+            if file.contains_key("schema_version") && file.contains_key("data") =>
+        {
+            let version = file
+                .get("schema_version")
+                .and_then(|v| v.as_u64())
+                .ok_or_else(|| {
+                    Errcode::DataCorrupted(
+                        fname.display().to_string(),
+                        "Invalid schema version".to_string(),
+                    )
+                })?;
+            (version as usize, file.remove("data").unwrap())
+        }
+        content => (BASE_VERSION, content),
+    };
+    kind.check_version(version)?;
+    for record in kind.file_records(&mut data) {
+        kind.migrate_record(record, version)?;
+    }
+    let data = serde_json::from_value::<T>(data).map_err(corrupted)?;
+
+    if (version < kind.version()) && !read_only {
+        println!(
+            "[*] Migrating {kind} data from version {version} to {}",
+            kind.version()
+        );
+        backup_file(fname)?;
+        write_atomic(fname, serialize(kind, &data)?.as_bytes())?;
+    }
+    Ok(Some(data))
+}
+
+impl Storage for JsonStorage {
+    fn load_contacts(&self) -> Result<ContactBook, Errcode> {
+        let kind = DataKind::Contacts;
+        Ok(load(&self.fname(kind), kind, self.read_only)?.unwrap_or_default())
+    }
+
+    fn load_invoices(&self) -> Result<InvoiceSavedData, Errcode> {
+        let kind = DataKind::Invoices;
+        Ok(load(&self.fname(kind), kind, self.read_only)?.unwrap_or_else(InvoiceSavedData::init))
+    }
+
+    fn load_quotations(&self) -> Result<QuotationSavedData, Errcode> {
+        let kind = DataKind::Quotations;
+        Ok(load(&self.fname(kind), kind, self.read_only)?.unwrap_or_else(QuotationSavedData::init))
+    }
+
+    fn save(&mut self, data: &Datastore) -> Result<(), Errcode> {
+        commit_files(vec![
+            (
+                self.fname(DataKind::Invoices),
+                serialize(DataKind::Invoices, &data.invoices)?,
+            ),
+            (
+                self.fname(DataKind::Quotations),
+                serialize(DataKind::Quotations, &data.quotations)?,
+            ),
+            (
+                self.fname(DataKind::Contacts),
+                serialize(DataKind::Contacts, &data.contacts)?,
+            ),
+        ])
+    }
+
+    fn is_empty(&self) -> Result<bool, Errcode> {
+        Ok(DataKind::ALL.iter().all(|k| !self.fname(*k).exists()))
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs::{File, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Errcode;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct LockHolder {
+    user: String,
+    host: String,
+    pid: u32,
+    since: DateTime<Utc>,
+}
+
+impl LockHolder {
+    fn current() -> LockHolder {
+        LockHolder {
+            user: current_user(),
+            host: current_host(),
+            pid: std::process::id(),
+            since: Utc::now(),
+        }
+    }
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}@{} (pid {}) since {}",
+            self.user,
+            self.host,
+            self.pid,
+            self.since.format("%Y-%m-%d %H:%M:%S UTC")
+        )
+    }
+}
+
+pub fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn current_host() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Advisory lock on the data directory, held until dropped.
+/// The lock file is locked by the OS, which releases it when the process holding it
+/// dies, so a lock left by a crash never blocks the next runs. The file itself is kept,
+/// it only tells who holds the lock.
+pub struct DataLock {
+    fname: PathBuf,
+    file: File,
+}
+
+impl DataLock {
+    pub fn acquire(datadir: &Path) -> Result<DataLock, Errcode> {
+        if !datadir.exists() {
+            std::fs::create_dir_all(datadir)?;
+        }
+        let fname = datadir.join("docgen.lock");
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&fname)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = std::fs::read_to_string(&fname)
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<LockHolder>(&content).ok());
+                return Err(Errcode::DataLocked(match holder {
+                    Some(holder) => holder.to_string(),
+                    None => "another process".to_string(),
+                }));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        file.set_len(0)?;
+        file.write_all(serde_json::to_string(&LockHolder::current())?.as_bytes())?;
+        file.sync_all()?;
+        Ok(DataLock { fname, file })
+    }
+}
+
+impl Drop for DataLock {
+    fn drop(&mut self) {
+        if let Err(e) = self.file.set_len(0).and_then(|_| self.file.unlock()) {
+            println!("Unable to release the lock {:?}: {e}", self.fname);
+        }
+    }
+}
+
+#[test]
+fn lock_data_dir() {
+    let dir = std::env::temp_dir().join(format!("docgen_lock_{}", std::process::id()));
+    let lock = DataLock::acquire(&dir).unwrap();
+    assert!(matches!(
+        DataLock::acquire(&dir),
+        Err(Errcode::DataLocked(_))
+    ));
+    drop(lock);
+
+    // Lock file left by a process that died, nobody holds the lock anymore
+    let holder = LockHolder {
+        pid: 0,
+        ..LockHolder::current()
+    };
+    std::fs::write(
+        dir.join("docgen.lock"),
+        serde_json::to_string(&holder).unwrap(),
+    )
+    .unwrap();
+    let lock = DataLock::acquire(&dir).unwrap();
+    let content = std::fs::read_to_string(dir.join("docgen.lock")).unwrap();
+    let holder: LockHolder = serde_json::from_str(&content).unwrap();
+    assert_eq!(holder.pid, std::process::id());
+    drop(lock);
+    std::fs::remove_dir_all(dir).unwrap();
+}
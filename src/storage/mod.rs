@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+
+use crate::config::ConfigStore;
+use crate::contact::ContactBook;
+use crate::data::Datastore;
+use crate::doctype::invoice::InvoiceSavedData;
+use crate::doctype::quotation::QuotationSavedData;
+use crate::errors::Errcode;
+
+mod json;
+mod lock;
+mod schema;
+mod sqlite;
+
+pub use json::JsonStorage;
+pub use lock::{current_user, DataLock};
+pub use sqlite::SqliteStorage;
+
+pub trait Storage {
+    fn load_contacts(&self) -> Result<ContactBook, Errcode>;
+    fn load_invoices(&self) -> Result<InvoiceSavedData, Errcode>;
+    fn load_quotations(&self) -> Result<QuotationSavedData, Errcode>;
+
+    /// Saves the whole datastore, either everything is written or nothing is
+    fn save(&mut self, data: &Datastore) -> Result<(), Errcode>;
+
+    /// True if nothing was ever saved in this storage
+    fn is_empty(&self) -> Result<bool, Errcode>;
+}
+
+/// Copies a file aside before it gets modified by a migration
+pub fn backup_file(fname: &Path) -> Result<PathBuf, Errcode> {
+    let mut backup_fname = fname.file_name().unwrap_or_default().to_os_string();
+    backup_fname.push(format!(".{}.bak", Utc::now().format("%Y%m%d-%H%M%S")));
+    let backup = fname.with_file_name(backup_fname);
+    std::fs::copy(fname, &backup)?;
+    println!("[*] Backup of {fname:?} saved to {backup:?}");
+    Ok(backup)
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StorageBackend {
+    Json,
+    Sqlite,
+}
+
+impl StorageBackend {
+    pub fn from_config(cfg: &ConfigStore) -> Result<StorageBackend, Errcode> {
+        match cfg.get_str("storage", "backend") {
+            "json" => Ok(StorageBackend::Json),
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            b => Err(Errcode::InvalidConfig(
+                "storage",
+                format!("Unknown storage backend {b:?}"),
+            )),
+        }
+    }
+
+    pub fn open(&self, datadir: &Path) -> Result<Box<dyn Storage>, Errcode> {
+        if !datadir.exists() {
+            std::fs::create_dir_all(datadir)?;
+        }
+        Ok(match self {
+            StorageBackend::Json => Box::new(JsonStorage::new(datadir)),
+            StorageBackend::Sqlite => Box::new(SqliteStorage::open(datadir)?),
+        })
+    }
+
+    /// Opens the storage to read the data without holding the lock of the data directory,
+    /// the migrations of SQLite databases are written in a transaction of their own
+    pub fn open_read_only(&self, datadir: &Path) -> Result<Box<dyn Storage>, Errcode> {
+        match self {
+            StorageBackend::Json => Ok(Box::new(JsonStorage::read_only(datadir))),
+            StorageBackend::Sqlite => self.open(datadir),
+        }
+    }
+}
+
+impl std::fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageBackend::Json => write!(f, "json"),
+            StorageBackend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// Copies the whole datastore from a backend to another, and checks that nothing was lost
+pub fn migrate_store(
+    datadir: &Path,
+    from: StorageBackend,
+    to: StorageBackend,
+) -> Result<(), Errcode> {
+    if from == to {
+        return Err(Errcode::InvalidConfig(
+            "storage",
+            format!("Data is already stored using the {to} backend"),
+        ));
+    }
+    let src = from.open(datadir)?;
+    let data = Datastore::import(src.as_ref())?;
+
+    let mut dst = to.open(datadir)?;
+    if !dst.is_empty()? {
+        return Err(Errcode::StorageNotEmpty(to.to_string()));
+    }
+    data.export(dst.as_mut())?;
+
+    let copy = Datastore::import(dst.as_ref())?;
+    if copy.as_json()? != data.as_json()? {
+        return Err(Errcode::StorageMigrationMismatch(
+            from.to_string(),
+            to.to_string(),
+        ));
+    }
+    Ok(())
+}
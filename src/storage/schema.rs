@@ -0,0 +1,191 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::config::ConfigStore;
+use crate::doctype::snapshot::LayoutSettings;
+use crate::doctype::DocumentType;
+use crate::errors::Errcode;
+use crate::lang::parse_legacy_date;
+
+/// Version of the data saved before versioning was introduced
+pub const BASE_VERSION: usize = 1;
+
+/// Converts a single element (contact, invoice, quotation) to the next version of its format
+pub type Migration = fn(&mut Value) -> Result<(), Errcode>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DataKind {
+    Contacts,
+    Invoices,
+    Quotations,
+}
+
+impl DataKind {
+    pub const ALL: [DataKind; 3] = [DataKind::Contacts, DataKind::Invoices, DataKind::Quotations];
+
+    /// The n-th migration converts an element from version `BASE_VERSION + n` to the next one
+    fn migrations(&self) -> &'static [Migration] {
+        match self {
+            DataKind::Contacts => &[],
+            DataKind::Invoices => &[iso_dates, invoice_layout],
+            DataKind::Quotations => &[iso_dates, quotation_layout],
+        }
+    }
+
+    pub fn version(&self) -> usize {
+        BASE_VERSION + self.migrations().len()
+    }
+
+    pub fn check_version(&self, version: usize) -> Result<(), Errcode> {
+        if (version < BASE_VERSION) || (version > self.version()) {
+            Err(Errcode::DataVersionUnsupported(self.to_string(), version))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn migrate_record(&self, record: &mut Value, from: usize) -> Result<(), Errcode> {
+        self.check_version(from)?;
+        apply_migrations(self.migrations(), record, from)
+    }
+
+    /// Gets all the elements stored in the content of a JSON data file
+    pub fn file_records<'a>(&self, data: &'a mut Value) -> Vec<&'a mut Value> {
+        match self {
+            DataKind::Contacts => data
+                .as_object_mut()
+                .map(|map| map.values_mut().collect())
+                .unwrap_or_default(),
+            DataKind::Invoices => data
+                .get_mut("history")
+                .and_then(|h| h.as_array_mut())
+                .map(|hist| hist.iter_mut().collect())
+                .unwrap_or_default(),
+            DataKind::Quotations => data
+                .get_mut("history")
+                .and_then(|h| h.as_object_mut())
+                .map(|hist| {
+                    hist.values_mut()
+                        .filter_map(|quotes| quotes.as_array_mut())
+                        .flat_map(|quotes| quotes.iter_mut())
+                        .filter_map(|quote| quote.get_mut(0))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Creation and sell dates were saved as text in the language of the documents, they are
+/// saved as ISO 8601 when they can be understood
+fn iso_dates(record: &mut Value) -> Result<(), Errcode> {
+    let text =
+        |record: &Value, key: &str| record.get(key).and_then(|v| v.as_str().map(String::from));
+    let created = text(record, "created_at")
+        .and_then(|date| date.parse::<DateTime<Utc>>().ok())
+        .map(|date| date.date_naive())
+        .or_else(|| text(record, "created").and_then(|date| parse_legacy_date(&date, true)));
+    if let Some(created) = created {
+        record["created"] = Value::from(created.to_string());
+    }
+    if let Some(date_sell) =
+        text(record, "date_sell").and_then(|date| parse_legacy_date(&date, false))
+    {
+        record["date_sell"] = Value::from(date_sell.to_string());
+    }
+    Ok(())
+}
+
+/// The whole configuration was saved with each document, only the settings changing its
+/// layout are kept, in its snapshot
+fn saved_layout(record: &mut Value, doctype: DocumentType) -> Result<(), Errcode> {
+    let config = record
+        .as_object_mut()
+        .and_then(|record| record.remove("config"))
+        .filter(|config| !config.is_null());
+    let (Some(config), Some(snapshot)) = (config, record.get_mut("snapshot")) else {
+        return Ok(());
+    };
+    if snapshot.is_object() {
+        let config: ConfigStore = serde_json::from_value(config)?;
+        let layout = LayoutSettings::from_config(&config.with_missing_settings(), doctype);
+        snapshot["layout"] = serde_json::to_value(layout)?;
+    }
+    Ok(())
+}
+
+fn invoice_layout(record: &mut Value) -> Result<(), Errcode> {
+    saved_layout(record, DocumentType::Invoice)
+}
+
+fn quotation_layout(record: &mut Value) -> Result<(), Errcode> {
+    saved_layout(record, DocumentType::Quotation)
+}
+
+fn apply_migrations(
+    migrations: &[Migration],
+    record: &mut Value,
+    from: usize,
+) -> Result<(), Errcode> {
+    for migration in migrations.iter().skip(from - BASE_VERSION) {
+        migration(record)?;
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for DataKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataKind::Contacts => write!(f, "contacts"),
+            DataKind::Invoices => write!(f, "invoices"),
+            DataKind::Quotations => write!(f, "quotations"),
+        }
+    }
+}
+
+#[test]
+fn migrations_chain() {
+    fn add_field(val: &mut Value) -> Result<(), Errcode> {
+        val["field"] = Value::from(1);
+        Ok(())
+    }
+    fn increment_field(val: &mut Value) -> Result<(), Errcode> {
+        val["field"] = Value::from(val["field"].as_u64().unwrap() + 1);
+        Ok(())
+    }
+    let chain: &[Migration] = &[add_field, increment_field, increment_field];
+
+    let mut record = serde_json::json!({});
+    apply_migrations(chain, &mut record, BASE_VERSION).unwrap();
+    assert_eq!(record["field"], 3);
+
+    let mut record = serde_json::json!({"field": 10});
+    apply_migrations(chain, &mut record, BASE_VERSION + 2).unwrap();
+    assert_eq!(record["field"], 11);
+
+    let mut record = serde_json::json!({"field": 10});
+    apply_migrations(chain, &mut record, BASE_VERSION + 3).unwrap();
+    assert_eq!(record["field"], 10);
+}
+
+#[test]
+fn saved_config_to_layout() {
+    let config: toml::Value = toml::from_str("[invoice]\nfooter = \"Old footer\"").unwrap();
+    let mut record = serde_json::json!({
+        "id": 1,
+        "config": serde_json::to_value(config).unwrap(),
+        "snapshot": {"issuer": {}},
+    });
+    invoice_layout(&mut record).unwrap();
+    assert!(record.get("config").is_none());
+    let layout: LayoutSettings =
+        serde_json::from_value(record["snapshot"]["layout"].clone()).unwrap();
+    assert_eq!(layout.footer, "Old footer");
+    assert!(layout.add_iban);
+    assert!(layout.qr_bill.is_none());
+
+    // Documents saved without a snapshot are rendered with the current configuration
+    let mut record = serde_json::json!({"id": 2, "config": null});
+    quotation_layout(&mut record).unwrap();
+    assert_eq!(record, serde_json::json!({"id": 2}));
+}
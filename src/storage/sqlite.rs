@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::contact::{Contact, ContactBook};
+use crate::data::Datastore;
+use crate::doctype::invoice::{InvoiceInput, InvoiceSavedData};
+use crate::doctype::quotation::{QuotationInput, QuotationSavedData};
+use crate::doctype::DocumentType;
+use crate::errors::Errcode;
+use crate::numbering::Counters;
+
+use super::schema::{DataKind, BASE_VERSION};
+use super::{backup_file, Storage};
+
+// Each element is serialized as JSON in the `data` column, the other columns are
+// copies of some fields, so they can be indexed.
+// Migrations are applied in order, the index of the last one applied is stored in the
+// `user_version` pragma of the database.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE counters (
+        name TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    CREATE TABLE contacts (
+        slug TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE invoices (
+        position INTEGER PRIMARY KEY,
+        id INTEGER NOT NULL,
+        recipient TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX invoices_recipient ON invoices(recipient);
+    CREATE TABLE quotations (
+        recipient TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        invoice_nb INTEGER,
+        data TEXT NOT NULL,
+        PRIMARY KEY (recipient, position)
+    );
+",
+    "
+    CREATE TABLE schema_versions (
+        kind TEXT PRIMARY KEY,
+        version INTEGER NOT NULL
+    );
+",
+];
+
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    pub fn fname(root: &Path) -> PathBuf {
+        root.join("docgen").with_extension("sqlite")
+    }
+
+    pub fn open(root: &Path) -> Result<SqliteStorage, Errcode> {
+        let fname = Self::fname(root);
+        let mut backup_done = !fname.is_file();
+        let mut backup = || -> Result<(), Errcode> {
+            if !backup_done {
+                backup_file(&fname)?;
+                backup_done = true;
+            }
+            Ok(())
+        };
+
+        let mut conn = Connection::open(&fname)?;
+        apply_migrations(&mut conn, &mut backup)?;
+        for kind in DataKind::ALL {
+            migrate_records(&mut conn, kind, &mut backup)?;
+        }
+        Ok(SqliteStorage { conn })
+    }
+
+    fn get_counter(&self, name: &str) -> Result<Option<usize>, Errcode> {
+        let val: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT value FROM counters WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(val.map(|v| v as usize))
+    }
+
+    /// Counters of the numbering schemes, stored as `<doctype>:<key>`, or as
+    /// `<doctype>@<profile>:<key>` for the other issuer profiles
+    fn get_scheme_counters(
+        &self,
+        doctype: DocumentType,
+    ) -> Result<(Counters, HashMap<String, Counters>), Errcode> {
+        let prefix = doctype.to_string();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, value FROM counters WHERE substr(name, 1, ?2) = ?1")?;
+        let rows = stmt.query_map(params![prefix, prefix.len() as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        let mut counters = HashMap::new();
+        let mut profile_counters: HashMap<String, Counters> = HashMap::new();
+        for row in rows {
+            let (name, value) = row?;
+            let name = &name[prefix.len()..];
+            if let Some(key) = name.strip_prefix(':') {
+                counters.insert(key.to_string(), value as usize);
+            } else if let Some((profile, key)) =
+                name.strip_prefix('@').and_then(|n| n.split_once(':'))
+            {
+                profile_counters
+                    .entry(profile.to_string())
+                    .or_default()
+                    .insert(key.to_string(), value as usize);
+            }
+        }
+        Ok((counters, profile_counters))
+    }
+}
+
+fn table(kind: DataKind) -> &'static str {
+    match kind {
+        DataKind::Contacts => "contacts",
+        DataKind::Invoices => "invoices",
+        DataKind::Quotations => "quotations",
+    }
+}
+
+type BackupFn<'a> = dyn FnMut() -> Result<(), Errcode> + 'a;
+
+fn apply_migrations(conn: &mut Connection, backup: &mut BackupFn) -> Result<(), Errcode> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if (version as usize) < MIGRATIONS.len() {
+        backup()?;
+    }
+    let tx = conn.transaction()?;
+    for (n, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (n + 1) as i64)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Converts the elements stored in a table to the current version of their format
+fn migrate_records(
+    conn: &mut Connection,
+    kind: DataKind,
+    backup: &mut BackupFn,
+) -> Result<(), Errcode> {
+    let version: Option<i64> = conn
+        .query_row(
+            "SELECT version FROM schema_versions WHERE kind = ?1",
+            [kind.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let version = version.map(|v| v as usize).unwrap_or(BASE_VERSION);
+    kind.check_version(version)?;
+    if version == kind.version() {
+        return Ok(());
+    }
+
+    backup()?;
+    println!(
+        "[*] Migrating {kind} data from version {version} to {}",
+        kind.version()
+    );
+    let tx = conn.transaction()?;
+    {
+        let table = table(kind);
+        let mut stmt = tx.prepare(&format!("SELECT rowid, data FROM {table}"))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<(i64, String)>, rusqlite::Error>>()?;
+        let mut update = tx.prepare(&format!("UPDATE {table} SET data = ?1 WHERE rowid = ?2"))?;
+        for (rowid, data) in rows {
+            let mut record = serde_json::from_str(&data).map_err(|e| {
+                Errcode::DataCorrupted(format!("{table} row {rowid}"), e.to_string())
+            })?;
+            kind.migrate_record(&mut record, version)?;
+            update.execute(params![serde_json::to_string(&record)?, rowid])?;
+        }
+    }
+    set_version(&tx, kind)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn set_version(tx: &rusqlite::Transaction, kind: DataKind) -> Result<(), Errcode> {
+    tx.execute(
+        "INSERT OR REPLACE INTO schema_versions (kind, version) VALUES (?1, ?2)",
+        params![kind.to_string(), kind.version() as i64],
+    )?;
+    Ok(())
+}
+
+fn parse_record<T: serde::de::DeserializeOwned>(kind: DataKind, data: &str) -> Result<T, Errcode> {
+    serde_json::from_str(data)
+        .map_err(|e| Errcode::DataCorrupted(table(kind).to_string(), e.to_string()))
+}
+
+impl Storage for SqliteStorage {
+    fn load_contacts(&self) -> Result<ContactBook, Errcode> {
+        let mut book = ContactBook::default();
+        let mut stmt = self.conn.prepare("SELECT slug, data FROM contacts")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (slug, data) = row?;
+            book.insert(slug, parse_record::<Contact>(DataKind::Contacts, &data)?);
+        }
+        Ok(book)
+    }
+
+    fn load_invoices(&self) -> Result<InvoiceSavedData, Errcode> {
+        let mut invoices = InvoiceSavedData::init();
+        if let Some(counter) = self.get_counter(&DocumentType::Invoice.to_string())? {
+            invoices.id_counter = counter;
+        }
+        (invoices.counters, invoices.profile_counters) =
+            self.get_scheme_counters(DocumentType::Invoice)?;
+        let mut stmt = self
+            .conn
+            .prepare("SELECT data FROM invoices ORDER BY position")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        for row in rows {
+            invoices
+                .history
+                .push(parse_record::<InvoiceInput>(DataKind::Invoices, &row?)?);
+        }
+        Ok(invoices)
+    }
+
+    fn load_quotations(&self) -> Result<QuotationSavedData, Errcode> {
+        let mut quotations = QuotationSavedData::init();
+        if let Some(counter) = self.get_counter(&DocumentType::Quotation.to_string())? {
+            quotations.id_counter = counter;
+        }
+        (quotations.counters, quotations.profile_counters) =
+            self.get_scheme_counters(DocumentType::Quotation)?;
+        let mut history: HashMap<String, Vec<(QuotationInput, Option<usize>)>> = HashMap::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT recipient, invoice_nb, data FROM quotations ORDER BY recipient, position",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (recipient, invoice_nb, data) = row?;
+            let quote = parse_record::<QuotationInput>(DataKind::Quotations, &data)?;
+            history
+                .entry(recipient)
+                .or_default()
+                .push((quote, invoice_nb.map(|n| n as usize)));
+        }
+        quotations.history = history;
+        Ok(quotations)
+    }
+
+    fn save(&mut self, data: &Datastore) -> Result<(), Errcode> {
+        // Dropping the transaction without committing it rolls everything back
+        let tx = self.conn.transaction()?;
+        // The records removed since the last save must not be loaded again
+        tx.execute_batch(
+            "DELETE FROM counters; DELETE FROM contacts;
+            DELETE FROM invoices; DELETE FROM quotations;",
+        )?;
+        {
+            let mut stmt =
+                tx.prepare("INSERT OR REPLACE INTO counters (name, value) VALUES (?1, ?2)")?;
+            stmt.execute(params![
+                DocumentType::Invoice.to_string(),
+                data.invoices.id_counter as i64
+            ])?;
+            stmt.execute(params![
+                DocumentType::Quotation.to_string(),
+                data.quotations.id_counter as i64
+            ])?;
+            let scheme_counters = [
+                (DocumentType::Invoice, None, &data.invoices.counters),
+                (DocumentType::Quotation, None, &data.quotations.counters),
+            ]
+            .into_iter()
+            .chain(
+                data.invoices
+                    .profile_counters
+                    .iter()
+                    .map(|(p, c)| (DocumentType::Invoice, Some(p), c)),
+            )
+            .chain(
+                data.quotations
+                    .profile_counters
+                    .iter()
+                    .map(|(p, c)| (DocumentType::Quotation, Some(p), c)),
+            );
+            for (doctype, profile, counters) in scheme_counters {
+                let profile = profile.map(|p| format!("@{p}")).unwrap_or_default();
+                for (key, value) in counters.iter() {
+                    stmt.execute(params![format!("{doctype}{profile}:{key}"), *value as i64])?;
+                }
+            }
+
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO contacts (slug, name, data) VALUES (?1, ?2, ?3)",
+            )?;
+            for (slug, contact) in data.contacts.iter() {
+                stmt.execute(params![slug, contact.name, serde_json::to_string(contact)?])?;
+            }
+
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO invoices (position, id, recipient, data)
+                VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (pos, inv) in data.invoices.history.iter().enumerate() {
+                stmt.execute(params![
+                    pos as i64,
+                    inv.id as i64,
+                    inv.recipient,
+                    serde_json::to_string(inv)?
+                ])?;
+            }
+
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO quotations (recipient, position, id, invoice_nb, data)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for (recipient, quotes) in data.quotations.history.iter() {
+                for (pos, (quote, invoice_nb)) in quotes.iter().enumerate() {
+                    stmt.execute(params![
+                        recipient,
+                        pos as i64,
+                        quote.id as i64,
+                        invoice_nb.map(|n| n as i64),
+                        serde_json::to_string(quote)?
+                    ])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn is_empty(&self) -> Result<bool, Errcode> {
+        let nb: i64 = self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM counters) + (SELECT COUNT(*) FROM contacts)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(nb == 0)
+    }
+}
+
+#[test]
+fn sqlite_roundtrip() {
+    use crate::rates::RateTable;
+
+    let dir = std::env::temp_dir().join(format!("docgen_sqlite_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut data = Datastore {
+        contacts: ContactBook::default(),
+        invoices: serde_json::from_str(
+            r#"{"id_counter": 3, "history": [{"id": 2, "recipient": "acme", "quote_nb": 0,
+            "date_sell": "2 Mars 2024", "tx": [["Audit", 1.0, 500.0]], "tax_rate": 0.2,
+            "created": "4 Avril 2024"}]}"#,
+        )
+        .unwrap(),
+        quotations: serde_json::from_str(
+            r#"{"id_counter": 2, "history": {"acme": [[{"id": 1, "recipient": "acme",
+            "created": "1 Mars 2024", "tx": [["Audit", 1.0, 500.0]]}, 1]]}}"#,
+        )
+        .unwrap(),
+        audit: vec![],
+        rates: RateTable::default(),
+    };
+    data.contacts.insert(
+        "acme".to_string(),
+        serde_json::from_str(
+            r#"{"slug": "acme", "name": "ACME", "address": "1 rue X", "invoices": [2],
+            "quotations": [1]}"#,
+        )
+        .unwrap(),
+    );
+
+    let mut store = SqliteStorage::open(&dir).unwrap();
+    assert!(store.is_empty().unwrap());
+    data.export(&mut store).unwrap();
+    let copy = Datastore::import(&SqliteStorage::open(&dir).unwrap()).unwrap();
+    assert_eq!(copy.as_json().unwrap(), data.as_json().unwrap());
+
+    data.quotations.history.remove("acme");
+    data.export(&mut store).unwrap();
+    let copy = Datastore::import(&SqliteStorage::open(&dir).unwrap()).unwrap();
+    assert!(copy.quotations.history.is_empty());
+    assert_eq!(copy.as_json().unwrap(), data.as_json().unwrap());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -71,6 +71,24 @@ pub fn import_style(stylefile: &PathBuf) -> Result<Style, Errcode> {
     }
 }
 
+/// Applies the style settings of an issuer profile, key by key
+pub fn merge_style(style: &Style, overrides: &Style) -> Style {
+    let mut res = style.clone();
+    for (key, val) in overrides.iter() {
+        match (res.get_mut(key), val) {
+            (Some(Value::Table(table)), Value::Table(subtable)) => {
+                for (subkey, subval) in subtable.iter() {
+                    table.insert(subkey.clone(), subval.clone());
+                }
+            }
+            _ => {
+                res.insert(key.clone(), val.clone());
+            }
+        }
+    }
+    res
+}
+
 pub fn generate_style_variables(style: &Style, doctype: String) -> String {
     generate_variable_for_style(style, |key| key == &doctype)
 }
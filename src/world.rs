@@ -12,10 +12,11 @@ use typst::syntax::{FileId, Source, VirtualPath};
 use typst::text::{Font, FontBook};
 use typst::{Library, World};
 
-use crate::doctype::{DocumentType, TypstData};
+use crate::config::ConfigStore;
+use crate::doctype::TypstData;
 use crate::errors::Errcode;
 use crate::fonts::{get_all_fonts, import_fonts};
-use crate::style::{generate_style_variables, import_style};
+use crate::style::{generate_style_variables, import_style, merge_style};
 
 type AssetStore = HashMap<PathBuf, Bytes>;
 
@@ -24,16 +25,24 @@ pub struct TypstWorld {
     fonts: (Prehashed<FontBook>, Vec<Font>),
     library: Prehashed<Library>,
     style: Map<String, toml::Value>,
-    doctype: DocumentType,
+    /// Table of the style file with the settings of this kind of output
+    style_table: String,
     source: Option<Source>,
 }
 
 impl TypstWorld {
-    pub fn new(root: &Path, doctype: DocumentType) -> Result<TypstWorld, Errcode> {
+    pub fn new(root: &Path, style_table: &str, cfg: &ConfigStore) -> Result<TypstWorld, Errcode> {
         let style = import_style(&root.join("style.toml"))?;
 
         let fonts_dir = root.join("fonts");
         get_all_fonts(&style, &fonts_dir)?;
+        // The issuer profiles can use other fonts
+        for profile in cfg.profiles() {
+            let overrides = cfg.for_profile(&profile)?.style_overrides();
+            if overrides.contains_key("font_name") {
+                get_all_fonts(&overrides, &fonts_dir)?;
+            }
+        }
         let fonts = import_fonts(&fonts_dir)?;
         let font_book = FontBook::from_fonts(&fonts);
 
@@ -44,7 +53,7 @@ impl TypstWorld {
             fonts: (Prehashed::new(font_book), fonts),
             assets,
             style,
-            doctype,
+            style_table: style_table.to_string(),
             library: Prehashed::new(Library::default()),
             source: None,
         })
@@ -52,7 +61,11 @@ impl TypstWorld {
 
     pub fn compile(&mut self, source: TypstData) -> Result<Document, Errcode> {
         let source_id = FileId::new(None, VirtualPath::new("/source"));
-        let style_vars = generate_style_variables(&self.style, self.doctype.to_string());
+        for (path, data) in source.assets.into_iter() {
+            self.assets.insert(path, Bytes::from(data));
+        }
+        let style = merge_style(&self.style, &source.style);
+        let style_vars = generate_style_variables(&style, self.style_table.clone());
         println!("{style_vars}\n{}", source.code);
         let source = Source::new(source_id, format!("{style_vars}\n{}\n", source.code));
         self.source = Some(source);
@@ -66,7 +79,7 @@ impl TypstWorld {
             }
             Err(e) => {
                 println!("Source code:\n{}", self.main().text());
-                panic!("Typst compilation error: {e:?}");
+                Err(Errcode::TypstCompilation(format!("{e:?}")))
             }
         }
     }